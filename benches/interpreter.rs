@@ -0,0 +1,59 @@
+// Throughput benchmarks for the interpreter core, run with `cargo bench`.
+//
+// Scenarios:
+//   * mixed_rom    - arithmetic, Cxnn, memory stores/loads and a small sprite in a tight loop, reported
+//                    as instructions per second. The RNG is seeded so every run executes the same path.
+//   * full_screen_draw - a loop of 8x15 sprite draws covering the whole 64x32 screen, measures Dxyn
+//                    which dominates most games.
+//
+// Each iteration runs one frame of CYCLES_PER_FRAME instructions, criterion keeps the last results
+// under target/criterion and reports the change against them.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use rusty_calico_c8::{Chip8Config, Chip8Interpreter};
+
+const CYCLES_PER_FRAME: u32 = 1_000;
+
+// 0x200: V0 += 1, V1 = rand & 0x3F, V2 = V0 ^ V1, I = 0x300, store/load V0-V2,
+//        draw 1 line of the ROM's own bytes at (V1, V2), jump back to 0x200
+const MIXED_ROM: [u16; 10] = [
+    0x7001, 0xC13F, 0x8200, 0x8213, 0xA300, 0xF255, 0xF265, 0xA20E, 0xD121, 0x1200,
+];
+
+// 0x200: I = sprite at 0x220, then draw 8x15 sprites across the screen:
+//        for V1 in 0, 15, 30 (y) and V0 in 0..64 step 8 (x), starting over once done
+const FULL_SCREEN_DRAW_ROM: [u16; 16] = [
+    0xA220, 0x6000, 0x6100, 0xD01F, 0x7008, 0x3040, 0x1206, 0x6000,
+    0x710F, 0x312D, 0x1206, 0x1200, 0x0000, 0x0000, 0x0000, 0x0000,
+];
+
+// Loaded right after FULL_SCREEN_DRAW_ROM, at 0x220
+const FULL_SCREEN_SPRITE: [u8; 15] = [0xFF; 15];
+
+fn rom_bytes(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+fn bench_rom(c: &mut Criterion, name: &str, rom: &[u8]) {
+    let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+    interpreter.load_rom_bytes(rom).unwrap();
+    interpreter.seed_rng(0x5EED);
+
+    let mut group = c.benchmark_group("interpreter");
+    group.throughput(Throughput::Elements(CYCLES_PER_FRAME as u64));
+    group.bench_function(name, |b| b.iter(|| interpreter.run_frame(CYCLES_PER_FRAME).unwrap()));
+    group.finish();
+}
+
+fn mixed_rom(c: &mut Criterion) {
+    bench_rom(c, "mixed_rom", &rom_bytes(&MIXED_ROM));
+}
+
+fn full_screen_draw(c: &mut Criterion) {
+    bench_rom(c, "full_screen_draw", &[rom_bytes(&FULL_SCREEN_DRAW_ROM), FULL_SCREEN_SPRITE.to_vec()].concat());
+}
+
+criterion_group!(benches, mixed_rom, full_screen_draw);
+criterion_main!(benches);
@@ -1,17 +1,42 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
 use sdl2::audio::AudioCallback;
 
+// The sound timer counts down at 60hz
+const SOUND_TIMER_FREQUENCY: u32 = 60;
+
+// How many samples the beep still lasts for, so it ends exactly when the sound timer reaches zero
+pub fn remaining_samples(sound_timer: u8, sample_rate: i32) -> u32 {
+    sound_timer as u32 * sample_rate.max(0) as u32 / SOUND_TIMER_FREQUENCY
+}
+
 pub struct SquareWave {
     phase_inc: f32,
     phase: f32,
     volume: f32,
+    // Set by the emulator from the sound timer, counted down here, silence once it's zero
+    remaining_samples: Arc<AtomicU32>,
 }
 
 impl AudioCallback for SquareWave {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
+        // Taken in one go, the emulator may store a fresh count in the meantime
+        let remaining = self.remaining_samples
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |remaining| {
+                Some(remaining.saturating_sub(out.len() as u32))
+            })
+            .unwrap_or(0);
+
         // Generate a square wave
-        for x in out.iter_mut() {
+        for (i, x) in out.iter_mut().enumerate() {
+            if i as u32 >= remaining {
+                *x = 0.0;
+                continue;
+            }
+
             *x = match self.phase {
                 x if x > 0.0 && x < 0.5 => self.volume,
                 _ => -self.volume
@@ -22,11 +47,37 @@ impl AudioCallback for SquareWave {
 }
 
 impl SquareWave {
-    pub fn new(phase_inc: f32, phase: f32, volume: f32) -> SquareWave {
+    pub fn new(phase_inc: f32, phase: f32, volume: f32, remaining_samples: Arc<AtomicU32>) -> SquareWave {
         SquareWave {
             phase_inc,
             phase,
-            volume
+            volume,
+            remaining_samples
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remaining_samples() {
+        assert_eq!(remaining_samples(0, 44100), 0);
+        assert_eq!(remaining_samples(1, 44100), 735);
+        assert_eq!(remaining_samples(60, 44100), 44100);
+        assert_eq!(remaining_samples(255, 48000), 204000);
+    }
+
+    #[test]
+    fn test_callback_stops_after_remaining_samples() {
+        let remaining = Arc::new(AtomicU32::new(3));
+        let mut square_wave = SquareWave::new(0.25, 0.25, 0.5, remaining.clone());
+        let mut out = [1.0f32; 5];
+
+        square_wave.callback(&mut out);
+
+        assert_eq!(out, [0.5, -0.5, -0.5, 0.0, 0.0]);
+        assert_eq!(remaining.load(Ordering::Acquire), 0);
+    }
+}
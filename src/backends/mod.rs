@@ -0,0 +1,3 @@
+// Frontends other than the default SDL one
+#[cfg(feature = "terminal")]
+pub mod terminal;
@@ -0,0 +1,279 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use rusty_calico_c8::{CalicoKey, Chip8Interpreter, FrameBuffer};
+
+use crate::ApplicationCmdSettings;
+use crate::emulator_error::EmulatorError;
+use crate::input_script;
+use crate::profile;
+use crate::rom_source;
+use crate::rpl_flags;
+use crate::state_dump;
+use crate::trace_file;
+use crate::trace_file::TraceWriter;
+
+// Terminals only report presses, so a key counts as held for this many frames after its last press.
+// Long enough to bridge the gap before the terminal's key repeat kicks in.
+const KEY_HOLD_FRAMES: u8 = 30;
+
+const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+
+// 0x1B, also the start of arrow key sequences so those quit as well
+const ESCAPE: u8 = 0x1B;
+// Raw mode swallows the signal, so Ctrl+C has to be handled by hand
+const CTRL_C: u8 = 0x03;
+
+// Two rows of pixels per line of text using half blocks, 64 x 16 characters (128 x 32 in hires)
+pub fn frame_buffer_to_string(frame_buffer: &FrameBuffer) -> String {
+    let (width, height) = frame_buffer.resolution();
+    let mut res = String::with_capacity(width as usize * height as usize / 2 * 3 + height as usize);
+
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let top = frame_buffer.pixel_value(x, y) != 0;
+            let bottom = frame_buffer.pixel_value(x, y + 1) != 0;
+
+            res.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+
+        // Raw mode doesn't turn '\n' into a carriage return
+        res.push_str("\r\n");
+    }
+
+    res
+}
+
+// Which hex key a typed character stands for, 'keymap' works as in the SDL backend but only
+// single character key names can be typed in a terminal
+pub fn hex_key_from_char(c: char, keymap: Option<&[String]>) -> Option<u8> {
+    let c = c.to_ascii_uppercase();
+
+    match keymap {
+        Some(keymap) => keymap.iter()
+            .position(|name| name.len() == 1 && name.eq_ignore_ascii_case(&c.to_string()))
+            .map(|hex| hex as u8),
+        None => (0..16).find(|hex| CalicoKey::from_hex(*hex).label() == c.to_string())
+    }
+}
+
+fn set_raw_mode(enabled: bool) -> Result<(), String> {
+    let mode: &[&str] = if enabled { &["raw", "-echo"] } else { &["-raw", "echo"] };
+
+    Command::new("stty")
+        .args(mode)
+        .stdin(Stdio::inherit())
+        .status()
+        .map_err(|e| format!("Unable to switch terminal mode: {}", e))
+        .map(|_| ())
+}
+
+// Stdin reads block, so they happen on their own thread
+fn spawn_input_reader() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+
+    // Unbuffered on purpose, every key has to arrive as soon as it's typed
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin();
+        let mut byte = [0u8; 1];
+
+        while let Ok(1) = stdin.read(&mut byte) {
+            if sender.send(byte[0]).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+// Only the first ROM is played, no ROM paths shows the test pattern instead
+pub fn run(parsed_args: &ApplicationCmdSettings) -> Result<(), EmulatorError> {
+    let mut interpreter = Chip8Interpreter::new(parsed_args.interpreter_config());
+
+    match parsed_args.rom_paths.first() {
+        Some(rom_path) => rom_source::load(&mut interpreter, rom_path)
+            .map_err(|e| EmulatorError::RomLoad(format!("Unable to load '{}': {}", rom_path, e)))?,
+        None => interpreter.load_test_pattern()
+    }
+
+    if let Some(path) = &parsed_args.rpl_flags_path {
+        rpl_flags::load(path, &mut interpreter)?;
+    }
+
+    let mut trace_writer = match &parsed_args.trace_path {
+        Some(path) => Some(trace_file::create(path)?),
+        None => None
+    };
+
+    set_raw_mode(true)?;
+
+    let res = run_loop(parsed_args, &mut interpreter, trace_writer.as_mut());
+
+    state_dump::dump_on_error(parsed_args.dump_state_path.as_deref(), &interpreter, &res);
+
+    // Leave the terminal usable even if the ROM failed
+    print!("\x1b[?25h\r\n");
+    set_raw_mode(false)?;
+
+    if parsed_args.profile {
+        for line in profile::format_opcode_counts(interpreter.opcode_counts()) {
+            println!("{}", line);
+        }
+    }
+
+    if let Some(trace_writer) = &mut trace_writer {
+        trace_writer.flush().map_err(|e| format!("Unable to write trace: {}", e))?;
+    }
+
+    if let Some(path) = &parsed_args.rpl_flags_path {
+        rpl_flags::save(path, &interpreter)?;
+    }
+
+    res
+}
+
+fn run_loop(parsed_args: &ApplicationCmdSettings, interpreter: &mut Chip8Interpreter,
+            mut trace_writer: Option<&mut TraceWriter<BufWriter<File>>>) -> Result<(), EmulatorError> {
+    let input = spawn_input_reader();
+    let mut stdout = std::io::stdout();
+
+    let input_script = match &parsed_args.input_script_path {
+        Some(path) => Some(input_script::open(path)?),
+        None => None
+    };
+
+    let mut held_frames = [0u8; 16];
+    // Keys the input script holds down, on top of the typed ones
+    let mut script_keys = [false; 16];
+    let mut frame_count: u64 = 0;
+    let mut was_playing_sound = false;
+    // Fraction of an instruction the clock speed owes, see next_frame_cycles()
+    let mut cycle_remainder = 0;
+
+    // Clear the screen and hide the cursor
+    print!("\x1b[2J\x1b[?25l");
+
+    let run_start = Instant::now();
+
+    loop {
+        let frame_start = Instant::now();
+
+        if parsed_args.max_runtime_duration().is_some_and(|max_runtime| run_start.elapsed() >= max_runtime) {
+            return Ok(());
+        }
+
+        for byte in input.try_iter() {
+            if byte == ESCAPE || byte == CTRL_C {
+                return Ok(());
+            }
+
+            if let Some(hex_key) = hex_key_from_char(byte as char, parsed_args.keymap.as_deref()) {
+                held_frames[hex_key as usize] = KEY_HOLD_FRAMES;
+            }
+        }
+
+        if let Some(input_script) = &input_script {
+            for event in input_script.events_at(frame_count) {
+                script_keys[event.hex_key as usize] = event.pressed;
+            }
+        }
+
+        frame_count += 1;
+
+        for (hex_key, frames) in held_frames.iter_mut().enumerate() {
+            interpreter.set_key(hex_key as u8, *frames > 0 || script_keys[hex_key]);
+            *frames = frames.saturating_sub(1);
+        }
+
+        let frame = interpreter.run_frame(parsed_args.next_frame_cycles(&mut cycle_remainder))?;
+
+        if let Some(trace_writer) = &mut trace_writer {
+            trace_writer.write_instructions(interpreter.drain_execution_log())
+                .map_err(|e| format!("Unable to write trace: {}", e))?;
+        }
+
+        // Terminal bell, once per beep
+        if frame.play_sound && !was_playing_sound {
+            print!("\x07");
+        }
+
+        was_playing_sound = frame.play_sound;
+
+        // The old picture may be wider than the new one, so it has to go first
+        if interpreter.take_resolution_changed() {
+            print!("\x1b[2J");
+        }
+
+        if frame.redraw {
+            // Move back to the top left and draw over the previous frame
+            print!("\x1b[H{}", frame_buffer_to_string(&interpreter.frame_buffer));
+            stdout.flush().map_err(|e| e.to_string())?;
+        }
+
+        if let Some(remaining) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frame_buffer_to_string() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.flip_pixel(0, 0);
+        frame_buffer.flip_pixel(1, 1);
+        frame_buffer.flip_pixel(2, 0);
+        frame_buffer.flip_pixel(2, 1);
+
+        let text = frame_buffer_to_string(&frame_buffer);
+        let lines: Vec<_> = text.split("\r\n").collect();
+
+        // 16 lines plus the empty string after the last line break
+        assert_eq!(lines.len(), 17);
+        assert!(lines[0].starts_with("▀▄█ "));
+        assert_eq!(lines[0].chars().count(), 64);
+        assert_eq!(lines[1], " ".repeat(64));
+    }
+
+    #[test]
+    fn test_frame_buffer_to_string_hires() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.set_hires(true);
+        frame_buffer.flip_pixel(127, 63);
+
+        let text = frame_buffer_to_string(&frame_buffer);
+        let lines: Vec<_> = text.split("\r\n").collect();
+
+        assert_eq!(lines.len(), 33);
+        assert_eq!(lines[31].chars().count(), 128);
+        assert!(lines[31].ends_with('▄'));
+    }
+
+    #[test]
+    fn test_hex_key_from_char() {
+        assert_eq!(hex_key_from_char('w', None), Some(0x5));
+        assert_eq!(hex_key_from_char('V', None), Some(0xF));
+        assert_eq!(hex_key_from_char('4', None), Some(0xC));
+        assert_eq!(hex_key_from_char('p', None), None);
+
+        let keymap: Vec<String> = "Space,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V".split(',').map(|key| key.to_owned()).collect();
+
+        assert_eq!(hex_key_from_char('1', Some(&keymap)), Some(0x1));
+        assert_eq!(hex_key_from_char(' ', Some(&keymap)), None);
+    }
+}
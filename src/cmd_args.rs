@@ -1,14 +1,26 @@
 use std::error::Error;
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use crate::cmd_args::CommandLineArgError::{InvalidArgument, InvalidArgumentOptionCount, InvalidArgumentOptionParse};
+use crate::cmd_args::CommandLineArgError::{DuplicateKeymapKey, InvalidArgument, InvalidArgumentOptionCount,
+                                           InvalidArgumentOptionParse, MutuallyExclusiveArguments, ReservedKeymapKey};
+use crate::rom_source;
+use rusty_calico_c8::{Chip8Config, ClipQuirk, KeyTriggerQuirk, MemoryQuirk, ShiftQuirk, TimingMode, VipDrawQuirk,
+                      WaitKeyQuirk};
+use rusty_calico_c8::interpreter::{COSMAC_VIP_STACK_LIMIT, DEFAULT_LOAD_ADDRESS, DEFAULT_MEMORY_SIZE, DEFAULT_STACK_LIMIT,
+                                   INTERPRETER_MEMORY_END, XO_CHIP_MEMORY_SIZE};
 
 #[derive(Debug, PartialEq)]
 pub enum CommandLineArgError<'a> {
     InvalidArgument { arg: &'a String },
     InvalidArgumentOptionCount { arg: &'a String },
     InvalidArgumentOptionParse { arg: &'a String, value: &'a str },
+    MutuallyExclusiveArguments { arg: &'a String, other: &'a String },
+    DuplicateKeymapKey { arg: &'a String, key: &'a str },
+    ReservedKeymapKey { arg: &'a String, key: &'a str },
 }
 
 impl Display for CommandLineArgError<'_> {
@@ -25,35 +37,392 @@ impl Display for CommandLineArgError<'_> {
             CommandLineArgError::InvalidArgumentOptionParse { arg, value } => {
                 write!(f, "Unable to parse argument's '{0}' option '{1}'", arg, value)
             }
+
+            CommandLineArgError::MutuallyExclusiveArguments { arg, other } => {
+                write!(f, "Argument '{0}' can't be used together with '{1}'", arg, other)
+            }
+
+            CommandLineArgError::DuplicateKeymapKey { arg, key } => {
+                write!(f, "Argument '{0}' maps key '{1}' more than once", arg, key)
+            }
+
+            CommandLineArgError::ReservedKeymapKey { arg, key } => {
+                write!(f, "Argument '{0}' maps key '{1}' which is reserved for an emulator hotkey", arg, key)
+            }
         }
     }
 }
 
 impl Error for CommandLineArgError<'_> {}
 
+// Coherent set of quirks matching what ROMs for a given platform expect
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum QuirkProfile {
+    Chip8,
+    Schip,
+    XoChip,
+}
+
+impl QuirkProfile {
+    pub fn from_name(name: &str) -> Option<QuirkProfile> {
+        match name {
+            "chip8" => Some(QuirkProfile::Chip8),
+            "schip" => Some(QuirkProfile::Schip),
+            "xochip" => Some(QuirkProfile::XoChip),
+            _ => None
+        }
+    }
+
+    fn apply(&self, settings: &mut ApplicationCmdSettings) {
+        let (shift_quirk, memory_quirk, clip_quirk, wait_key_quirk, display_wait, memory_size, stack_limit) = match *self {
+            QuirkProfile::Chip8 => (ShiftQuirk::ShiftVy, MemoryQuirk::IncrementI, ClipQuirk::Clip, WaitKeyQuirk::OnRelease,
+                                    true, DEFAULT_MEMORY_SIZE, COSMAC_VIP_STACK_LIMIT),
+            QuirkProfile::Schip => (ShiftQuirk::ShiftVx, MemoryQuirk::LeaveI, ClipQuirk::Clip, WaitKeyQuirk::OnPress,
+                                    false, DEFAULT_MEMORY_SIZE, DEFAULT_STACK_LIMIT),
+            QuirkProfile::XoChip => (ShiftQuirk::ShiftVy, MemoryQuirk::IncrementI, ClipQuirk::Wrap, WaitKeyQuirk::OnRelease,
+                                     false, XO_CHIP_MEMORY_SIZE, DEFAULT_STACK_LIMIT),
+        };
+
+        settings.shift_quirk = shift_quirk;
+        settings.memory_quirk = memory_quirk;
+        settings.clip_quirk = clip_quirk;
+        settings.wait_key_quirk = wait_key_quirk;
+        settings.display_wait = display_wait;
+        settings.memory_size = memory_size;
+        settings.stack_limit = stack_limit;
+    }
+}
+
+// Where the emulator draws and reads input from
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Backend {
+    Sdl,
+    // Needs the 'terminal' feature
+    Terminal,
+}
+
+pub const DEFAULT_PHOSPHOR_DECAY: u8 = 48;
+
+pub const DEFAULT_CLOCK_SPEED: u64 = 600;
+
+// Audio buffer sizes (in samples) -audio_latency accepts, smaller is snappier but may crackle
+pub const AUDIO_LATENCY_RANGE: RangeInclusive<u16> = 16..=8192;
+
+// Colors for pixel values 0-3, 0 is off, 1 and 2 are the first and second XO-CHIP plane and 3 is both
+pub const DEFAULT_PLANE_COLORS: [(u8, u8, u8); 4] = [(0, 0, 0), (255, 255, 255), (255, 0, 0), (0, 0, 255)];
+
+// Emulator hotkeys, a keymap can't take these over
+const RESERVED_KEYS: [&str; 13] = ["Escape", "M", "P", "N", "Tab", "=", "+", "-", "Keypad +", "Keypad -", "`", "F3", "F12"];
+
 #[derive(Debug, PartialEq)]
 pub struct ApplicationCmdSettings {
+    // The ROM in place of the first argument followed by any other paths given, empty for -test_pattern
+    pub rom_paths: Vec<String>,
     pub sound_enabled: bool,
+    // Audio buffer size in samples, a power of two, None = let SDL pick
+    pub audio_latency: Option<u16>,
     pub window_size_x: u32,
     pub window_size_y: u32,
+    pub integer_scale: bool,
+    // Dark gap between pixels when they're big enough
+    pub scanlines: bool,
+    // None = phosphor fade disabled, otherwise how much intensity unlit pixels lose per frame
+    pub phosphor_decay: Option<u8>,
+    // None = no dimming, otherwise how many frames an unlit pixel takes to go from dimmed to off, ignored
+    // when phosphor_decay is set
+    pub dim_decay: Option<u8>,
+    // Keep pixels lit for one extra frame, ignored when phosphor_decay or dim_decay is set
+    pub flicker_reduce: bool,
+    // 0 = uncapped, emulation itself always runs at 60 Hz regardless
+    pub fps_cap: u32,
+    // Seconds of wall clock time before quitting on its own, 0 = unlimited
+    pub max_runtime: u64,
     pub cpu_clock_speed: u64,
+    // Overrides cpu_clock_speed when set
+    pub instructions_per_frame: Option<u32>,
+    pub start_paused: bool,
+    pub record_path: Option<String>,
+    pub playback_path: Option<String>,
+    pub input_script_path: Option<String>,
+    pub dump_memory_path: Option<String>,
+    // Needs the serde feature
+    pub dump_state_path: Option<String>,
+    pub rpl_flags_path: Option<String>,
+    pub trace_path: Option<String>,
+    // SDL key names for hex keys 0 through F, None = built-in layout
+    pub keymap: Option<Vec<String>>,
+    pub plane_colors: [(u8, u8, u8); 4],
+    pub backend: Backend,
+    pub validate_only: bool,
+    pub info_only: bool,
+    pub identify_only: bool,
+    // Menu key to pick in the quirks test ROM, None = run normally
+    pub quirks_report: Option<u8>,
+    pub lenient: bool,
+    pub warn_vf: bool,
+    pub profile: bool,
+    // Print the screen as text to stdout when it changes
+    pub ascii_dump: bool,
+    pub legacy_sys: bool,
+    pub protect_interpreter_mem: bool,
+    pub clip_quirk: ClipQuirk,
+    pub shift_quirk: ShiftQuirk,
+    pub memory_quirk: MemoryQuirk,
+    pub wait_key_quirk: WaitKeyQuirk,
+    // Not part of any quirk profile, no platform did this, it works around OS key repeat
+    pub key_trigger_quirk: KeyTriggerQuirk,
+    // Not part of the chip8 profile either, only some games rely on it
+    pub vip_draw_quirk: VipDrawQuirk,
+    pub display_wait: bool,
+    // VipCycles ignores clock_speed and instructions_per_frame
+    pub timing_mode: TimingMode,
+    pub memory_size: usize,
+    pub stack_limit: usize,
+    pub load_address: u16,
+    // Header bytes skipped before the ROM is loaded
+    pub rom_offset: usize,
+    // Fixed RNG seed for Cxnn, None = random every run
+    pub seed: Option<u64>,
+}
+
+fn parse_timing_mode(name: &str) -> Option<TimingMode> {
+    match name {
+        "instructions" => Some(TimingMode::Instructions),
+        "vip" => Some(TimingMode::VipCycles),
+        _ => None
+    }
+}
+
+// 'RRGGBB', a leading '#' is allowed
+fn parse_hex_color(value: &str) -> Option<(u8, u8, u8)> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+    Some((channel(0)?, channel(2)?, channel(4)?))
 }
 
 impl ApplicationCmdSettings {
     pub fn new() -> ApplicationCmdSettings {
         ApplicationCmdSettings {
+            rom_paths: vec![],
             sound_enabled: true,
+            audio_latency: None,
             window_size_x: 640,
             window_size_y: 320,
-            cpu_clock_speed: 600,
+            integer_scale: false,
+            scanlines: false,
+            phosphor_decay: None,
+            dim_decay: None,
+            flicker_reduce: false,
+            fps_cap: 60,
+            max_runtime: 0,
+            cpu_clock_speed: DEFAULT_CLOCK_SPEED,
+            instructions_per_frame: None,
+            start_paused: false,
+            record_path: None,
+            playback_path: None,
+            input_script_path: None,
+            dump_memory_path: None,
+            dump_state_path: None,
+            rpl_flags_path: None,
+            trace_path: None,
+            keymap: None,
+            plane_colors: DEFAULT_PLANE_COLORS,
+            backend: Backend::Sdl,
+            validate_only: false,
+            info_only: false,
+            identify_only: false,
+            quirks_report: None,
+            lenient: false,
+            warn_vf: false,
+            profile: false,
+            ascii_dump: false,
+            legacy_sys: false,
+            protect_interpreter_mem: false,
+            clip_quirk: ClipQuirk::Wrap,
+            shift_quirk: ShiftQuirk::ShiftVx,
+            memory_quirk: MemoryQuirk::LeaveI,
+            wait_key_quirk: WaitKeyQuirk::OnPress,
+            key_trigger_quirk: KeyTriggerQuirk::Level,
+            vip_draw_quirk: VipDrawQuirk::Off,
+            display_wait: false,
+            timing_mode: TimingMode::Instructions,
+            memory_size: DEFAULT_MEMORY_SIZE,
+            stack_limit: DEFAULT_STACK_LIMIT,
+            load_address: DEFAULT_LOAD_ADDRESS,
+            rom_offset: 0,
+            seed: None,
+        }
+    }
+
+    pub fn new_from_args(args: &[String]) -> Result<ApplicationCmdSettings, CommandLineArgError> {
+        ApplicationCmdSettings::new().apply_args(args)
+    }
+
+    // Precedence is CLI args, then the config file, then the built-in defaults
+    pub fn from_config_and_args(config: Option<&str>, args: &[String]) -> Result<ApplicationCmdSettings, String> {
+        match config {
+            Some(text) => ApplicationCmdSettings::new_from_config(text)?.apply_args(args),
+            None => ApplicationCmdSettings::new_from_args(args)
+        }.map_err(|e| e.to_string())
+    }
+
+    // Reads the file given with -config:<path>, or the default config if there is one.
+    // A missing default config is fine, a missing explicit one is an error.
+    pub fn load_config(args: &[String]) -> Result<Option<String>, String> {
+        let explicit_path = args.iter()
+            .skip(2)
+            .find_map(|arg| arg.strip_prefix("-config:"));
+
+        match explicit_path {
+            Some(path) => std::fs::read_to_string(path)
+                .map(Some)
+                .map_err(|e| format!("Unable to read config '{}': {}", path, e)),
+
+            None => Ok(ApplicationCmdSettings::default_config_path()
+                .and_then(|path| std::fs::read_to_string(path).ok()))
         }
     }
 
-    pub fn new_from_args(args: &Vec<String>) -> Result<ApplicationCmdSettings, CommandLineArgError> {
+    fn default_config_path() -> Option<PathBuf> {
+        let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config")
+        };
+
+        Some(config_dir.join("rusty-calico-c8").join("config.toml"))
+    }
+
+    // Supports the flat 'key = value' subset of TOML, values are integers, booleans or "strings"
+    pub fn new_from_config(text: &str) -> Result<ApplicationCmdSettings, String> {
         let mut res = ApplicationCmdSettings::new();
+        let mut clock_speed_set = false;
+        let mut instructions_per_frame_set = false;
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid_line = || format!("Invalid config line {}: '{}'", line_number + 1, line);
+
+            let (key, value) = line.split_once('=').ok_or_else(invalid_line)?;
+            let key = key.trim();
+            let value = value.trim().trim_matches('"');
+
+            let parse_error = || format!("Invalid value '{}' for config key '{}'", value, key);
+
+            match key {
+                "sound" => res.sound_enabled = value.parse().map_err(|_| parse_error())?,
+                "window_size_x" => res.window_size_x = value.parse().map_err(|_| parse_error())?,
+                "window_size_y" => res.window_size_y = value.parse().map_err(|_| parse_error())?,
+                "integer_scale" => res.integer_scale = value.parse().map_err(|_| parse_error())?,
+                "scanlines" => res.scanlines = value.parse().map_err(|_| parse_error())?,
+                "phosphor_decay" => res.phosphor_decay = Some(value.parse().map_err(|_| parse_error())?),
+                "dim_decay" => res.dim_decay = Some(value.parse().map_err(|_| parse_error())?),
+                "flicker_reduce" => res.flicker_reduce = value.parse().map_err(|_| parse_error())?,
+                "fps_cap" => res.fps_cap = value.parse().map_err(|_| parse_error())?,
+                "max_runtime" => res.max_runtime = value.parse().map_err(|_| parse_error())?,
+                "clock_speed" => {
+                    res.cpu_clock_speed = value.parse().map_err(|_| parse_error())?;
+                    clock_speed_set = true;
+                }
+
+                "instructions_per_frame" => {
+                    res.instructions_per_frame = Some(value.parse().map_err(|_| parse_error())?);
+                    instructions_per_frame_set = true;
+                }
+
+                "lenient" => res.lenient = value.parse().map_err(|_| parse_error())?,
+                "warn_vf" => res.warn_vf = value.parse().map_err(|_| parse_error())?,
+                "legacy_sys" => res.legacy_sys = value.parse().map_err(|_| parse_error())?,
+                "protect_interpreter_mem" => res.protect_interpreter_mem = value.parse().map_err(|_| parse_error())?,
+
+                "quirks" => QuirkProfile::from_name(value).ok_or_else(parse_error)?.apply(&mut res),
+                "timing" => res.timing_mode = parse_timing_mode(value).ok_or_else(parse_error)?,
+
+                // Same format as -plane_colors, e.g. "000000:FFFFFF:FF0000:0000FF"
+                "colors" => {
+                    let colors: Vec<_> = value.split(':').map(parse_hex_color).collect();
+
+                    if colors.len() != res.plane_colors.len() {
+                        return Err(parse_error());
+                    }
+
+                    for (i, color) in colors.into_iter().enumerate() {
+                        res.plane_colors[i] = color.ok_or_else(parse_error)?;
+                    }
+                }
+
+                "clip" => {
+                    let clip: bool = value.parse().map_err(|_| parse_error())?;
+
+                    res.clip_quirk = if clip { ClipQuirk::Clip } else { ClipQuirk::Wrap };
+                }
+
+                "wait_release" => {
+                    let wait_release: bool = value.parse().map_err(|_| parse_error())?;
+
+                    res.wait_key_quirk = if wait_release { WaitKeyQuirk::OnRelease } else { WaitKeyQuirk::OnPress };
+                }
+
+                "vip_draw" => {
+                    let vip_draw: bool = value.parse().map_err(|_| parse_error())?;
+
+                    res.vip_draw_quirk = if vip_draw { VipDrawQuirk::WrapRightClipBottom } else { VipDrawQuirk::Off };
+                }
+
+                "edge_keys" => {
+                    let edge_keys: bool = value.parse().map_err(|_| parse_error())?;
+
+                    res.key_trigger_quirk = if edge_keys { KeyTriggerQuirk::Edge } else { KeyTriggerQuirk::Level };
+                }
+
+                _ => return Err(format!("Unknown config key '{}'", key))
+            }
+        }
+
+        // Same rule as -clock_speed and -instructions_per_frame on the command line
+        if clock_speed_set && instructions_per_frame_set {
+            return Err("Config keys 'clock_speed' and 'instructions_per_frame' can't be used together".to_owned());
+        }
+
+        Ok(res)
+    }
+
+    fn apply_args(mut self, args: &[String]) -> Result<ApplicationCmdSettings, CommandLineArgError<'_>> {
+        let res = &mut self;
+
+        // Individual quirk flags win over the profile no matter the order they were given in
+        let mut clip_override = None;
+        let mut wait_key_override = None;
+
+        let mut clock_speed_arg = None;
+        let mut instructions_per_frame_arg = None;
+        let mut window_size_arg = None;
+        let mut scale_arg = None;
+        let mut load_address_arg = None;
 
         for (i, arg) in args.iter().enumerate() {
-            if i == 0 || i == 1 {
+            if i == 0 {
+                continue;
+            }
+
+            // Anything that isn't a flag is another ROM for the playlist, -keys and -test_pattern aren't ROMs
+            if !arg.starts_with('-') || rom_source::is_stdin(arg) {
+                res.rom_paths.push(arg.clone());
+
+                continue;
+            }
+
+            if i == 1 {
                 continue;
             }
 
@@ -68,6 +437,60 @@ impl ApplicationCmdSettings {
                     res.sound_enabled = false;
                 }
 
+                "-audio_latency" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    match arg_tokens[1].parse::<u16>() {
+                        Ok(val) if val.is_power_of_two() && AUDIO_LATENCY_RANGE.contains(&val) => res.audio_latency = Some(val),
+                        _ => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
+                }
+
+                "-clip" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    clip_override = Some(ClipQuirk::Clip);
+                }
+
+                "-wait_release" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    wait_key_override = Some(WaitKeyQuirk::OnRelease);
+                }
+
+                "-vip_draw" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.vip_draw_quirk = VipDrawQuirk::WrapRightClipBottom;
+                }
+
+                "-edge_keys" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.key_trigger_quirk = KeyTriggerQuirk::Edge;
+                }
+
+                "-quirks" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    match QuirkProfile::from_name(arg_tokens[1]) {
+                        Some(profile) => profile.apply(res),
+                        None => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
+                }
+
                 "-clock_speed" => {
                     if arg_tokens.len() != 2 {
                         return Err(InvalidArgumentOptionCount { arg });
@@ -77,6 +500,25 @@ impl ApplicationCmdSettings {
                         Ok(val) => res.cpu_clock_speed = val,
                         Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
                     }
+
+                    // Otherwise instructions_per_frame from the config file would still win
+                    res.instructions_per_frame = None;
+                    clock_speed_arg = Some(arg);
+                }
+
+                "-instructions_per_frame" | "-ipf" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    match arg_tokens[1].parse() {
+                        Ok(val) => res.instructions_per_frame = Some(val),
+                        Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
+
+                    // A clock speed from the config file mustn't leave its remainder in next_frame_cycles()
+                    res.cpu_clock_speed = DEFAULT_CLOCK_SPEED;
+                    instructions_per_frame_arg = Some(arg);
                 }
 
                 "-window_size" => {
@@ -93,31 +535,1117 @@ impl ApplicationCmdSettings {
                         Ok(val) => res.window_size_y = val,
                         Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[2] })
                     }
+
+                    window_size_arg = Some(arg);
                 }
 
-                _ => return Err(InvalidArgument { arg })
-            }
-        }
+                // Shorthand for -window_size:64x:32x
+                "-scale" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
 
-        Ok(res)
-    }
-}
+                    let size = arg_tokens[1].parse::<u32>().ok()
+                        .filter(|scale| *scale != 0)
+                        .and_then(|scale| Some((scale.checked_mul(64)?, scale.checked_mul(32)?)));
 
-#[cfg(test)]
-mod test {
-    use super::*;
+                    match size {
+                        Some((x, y)) => {
+                            res.window_size_x = x;
+                            res.window_size_y = y;
+                        }
+                        None => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
 
-    #[test]
-    fn new_from_args_valid_test() {
-        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-no_sound".to_owned(), "-clock_speed:780".to_owned(),
-                                     "-window_size:1280:640".to_owned()];
+                    scale_arg = Some(arg);
+                }
 
-        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+                "-integer_scale" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
 
-        assert_eq!(res.cpu_clock_speed, 780);
-        assert_eq!(res.sound_enabled, false);
-        assert_eq!(res.window_size_x, 1280);
-        assert_eq!(res.window_size_y, 640);
+                    res.integer_scale = true;
+                }
+
+                "-scanlines" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.scanlines = true;
+                }
+
+                "-phosphor" => {
+                    match arg_tokens.len() {
+                        1 => res.phosphor_decay = Some(DEFAULT_PHOSPHOR_DECAY),
+
+                        2 => match arg_tokens[1].parse() {
+                            Ok(val) => res.phosphor_decay = Some(val),
+                            Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                        }
+
+                        _ => return Err(InvalidArgumentOptionCount { arg })
+                    }
+                }
+
+                "-dim_decay" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    match arg_tokens[1].parse() {
+                        Ok(val) => res.dim_decay = Some(val),
+                        Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
+                }
+
+                "-flicker_reduce" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.flicker_reduce = true;
+                }
+
+                "-fps_cap" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    match arg_tokens[1].parse() {
+                        Ok(val) => res.fps_cap = val,
+                        Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
+                }
+
+                "-max_runtime" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    match arg_tokens[1].parse() {
+                        Ok(val) => res.max_runtime = val,
+                        Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
+                }
+
+                "-start_paused" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.start_paused = true;
+                }
+
+                // Paths can contain ':' themselves (C:\...), so everything after the first one is the path
+                "-record" => {
+                    if arg_tokens.len() < 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.record_path = Some(arg_tokens[1..].join(":"));
+                }
+
+                "-playback" => {
+                    if arg_tokens.len() < 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.playback_path = Some(arg_tokens[1..].join(":"));
+                }
+
+                "-input_script" => {
+                    if arg_tokens.len() < 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.input_script_path = Some(arg_tokens[1..].join(":"));
+                }
+
+                "-dump_memory_on_exit" => {
+                    if arg_tokens.len() < 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.dump_memory_path = Some(arg_tokens[1..].join(":"));
+                }
+
+                "-rpl_flags" => {
+                    if arg_tokens.len() < 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.rpl_flags_path = Some(arg_tokens[1..].join(":"));
+                }
+
+                "-dump_state_on_error" => {
+                    if arg_tokens.len() < 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.dump_state_path = Some(arg_tokens[1..].join(":"));
+                }
+
+                "-trace" => {
+                    if arg_tokens.len() < 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.trace_path = Some(arg_tokens[1..].join(":"));
+                }
+
+                "-keymap" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    let keys: Vec<_> = arg_tokens[1].split(',').map(|key| key.trim()).collect();
+
+                    if keys.len() != 16 || keys.iter().any(|key| key.is_empty()) {
+                        return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] });
+                    }
+
+                    // SDL key names are case insensitive
+                    for (i, key) in keys.iter().enumerate() {
+                        if RESERVED_KEYS.iter().any(|reserved| reserved.eq_ignore_ascii_case(key)) {
+                            return Err(ReservedKeymapKey { arg, key });
+                        }
+
+                        if keys[..i].iter().any(|other| other.eq_ignore_ascii_case(key)) {
+                            return Err(DuplicateKeymapKey { arg, key });
+                        }
+                    }
+
+                    res.keymap = Some(keys.iter().map(|key| key.to_string()).collect());
+                }
+
+                "-plane_colors" => {
+                    if arg_tokens.len() != 5 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    for (i, value) in arg_tokens[1..].iter().enumerate() {
+                        match parse_hex_color(value) {
+                            Some(color) => res.plane_colors[i] = color,
+                            None => return Err(InvalidArgumentOptionParse { arg, value })
+                        }
+                    }
+                }
+
+                "-timing" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.timing_mode = parse_timing_mode(arg_tokens[1])
+                        .ok_or(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })?;
+                }
+
+                "-backend" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.backend = match arg_tokens[1] {
+                        "sdl" => Backend::Sdl,
+                        "terminal" => Backend::Terminal,
+                        _ => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    };
+                }
+
+                "-validate" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.validate_only = true;
+                }
+
+                "-info" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.info_only = true;
+                }
+
+                "-identify" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.identify_only = true;
+                }
+
+                "-quirks_report" => {
+                    res.quirks_report = match arg_tokens.len() {
+                        1 => Some(1),
+                        2 => match u8::from_str_radix(arg_tokens[1], 16) {
+                            Ok(val) if val <= 0xF => Some(val),
+                            _ => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                        },
+                        _ => return Err(InvalidArgumentOptionCount { arg })
+                    };
+                }
+
+                "-lenient" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.lenient = true;
+                }
+
+                "-warn_vf" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.warn_vf = true;
+                }
+
+                "-ascii_dump" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.ascii_dump = true;
+                }
+
+                "-profile" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.profile = true;
+                }
+
+                "-legacy_sys" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.legacy_sys = true;
+                }
+
+                "-protect_interpreter_mem" => {
+                    if arg_tokens.len() != 1 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    res.protect_interpreter_mem = true;
+                }
+
+                "-seed" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    match arg_tokens[1].parse() {
+                        Ok(val) => res.seed = Some(val),
+                        Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
+                }
+
+                // Hex, with or without 0x
+                "-load_address" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    match u16::from_str_radix(arg_tokens[1].trim_start_matches("0x"), 16) {
+                        Ok(val) => res.load_address = val,
+                        Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
+
+                    load_address_arg = Some((arg, arg_tokens[1]));
+                }
+
+                "-rom_offset" => {
+                    if arg_tokens.len() != 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+
+                    match arg_tokens[1].parse() {
+                        Ok(val) => res.rom_offset = val,
+                        Err(_) => return Err(InvalidArgumentOptionParse { arg, value: arg_tokens[1] })
+                    }
+                }
+
+                // Already picked up by load_config()
+                "-config" => {
+                    if arg_tokens.len() < 2 {
+                        return Err(InvalidArgumentOptionCount { arg });
+                    }
+                }
+
+                _ => return Err(InvalidArgument { arg })
+            }
+        }
+
+        if let (Some(arg), Some(other)) = (instructions_per_frame_arg, clock_speed_arg) {
+            return Err(MutuallyExclusiveArguments { arg, other });
+        }
+
+        if let (Some(arg), Some(other)) = (scale_arg, window_size_arg) {
+            return Err(MutuallyExclusiveArguments { arg, other });
+        }
+
+        // Checked last, -quirks:xochip may still grow the memory
+        if let Some((arg, value)) = load_address_arg {
+            if !res.load_address.is_multiple_of(2) || res.load_address < INTERPRETER_MEMORY_END ||
+                res.load_address as usize >= res.memory_size {
+                return Err(InvalidArgumentOptionParse { arg, value });
+            }
+        }
+
+        if let Some(clip_quirk) = clip_override {
+            res.clip_quirk = clip_quirk;
+        }
+
+        if let Some(wait_key_quirk) = wait_key_override {
+            res.wait_key_quirk = wait_key_quirk;
+        }
+
+        Ok(self)
+    }
+
+    pub fn interpreter_config(&self) -> Chip8Config {
+        Chip8Config {
+            sound_enabled: self.sound_enabled,
+            shift_quirk: self.shift_quirk,
+            memory_quirk: self.memory_quirk,
+            clip_quirk: self.clip_quirk,
+            wait_key_quirk: self.wait_key_quirk,
+            key_trigger_quirk: self.key_trigger_quirk,
+            vip_draw_quirk: self.vip_draw_quirk,
+            display_wait: self.display_wait,
+            timing_mode: self.timing_mode,
+            lenient: self.lenient,
+            warn_vf: self.warn_vf,
+            profile: self.profile,
+            legacy_sys: self.legacy_sys,
+            protect_interpreter_mem: self.protect_interpreter_mem,
+            log_executions: self.trace_path.is_some(),
+            load_address: self.load_address,
+            rom_offset: self.rom_offset,
+            rng_seed: self.seed,
+            memory_size: self.memory_size,
+            stack_limit: self.stack_limit,
+        }
+    }
+}
+
+// Only frontends run frames, a build without any still parses every argument
+#[cfg_attr(not(any(feature = "sdl", feature = "terminal")), allow(dead_code))]
+impl ApplicationCmdSettings {
+    pub fn cycles_per_frame(&self) -> u32 {
+        match self.instructions_per_frame {
+            Some(val) => val,
+            None => (self.cpu_clock_speed / 60) as u32
+        }
+    }
+
+    // -max_runtime, None when unlimited
+    pub fn max_runtime_duration(&self) -> Option<Duration> {
+        (self.max_runtime > 0).then(|| Duration::from_secs(self.max_runtime))
+    }
+
+    // cycles_per_frame() plus the part of the clock speed that doesn't divide by 60, carried over in
+    // 'remainder' (60ths of an instruction) until it adds up, so -clock_speed:700 averages 700 instead of 660
+    pub fn next_frame_cycles(&self, remainder: &mut u64) -> u32 {
+        if self.instructions_per_frame.is_none() {
+            *remainder += self.cpu_clock_speed % 60;
+        }
+
+        let carried = *remainder / 60;
+        *remainder %= 60;
+
+        self.cycles_per_frame() + carried as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn new_from_args_valid_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-no_sound".to_owned(), "-clock_speed:780".to_owned(),
+                                     "-window_size:1280:640".to_owned(), "-integer_scale".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.cpu_clock_speed, 780);
+        assert_eq!(res.sound_enabled, false);
+        assert_eq!(res.window_size_x, 1280);
+        assert_eq!(res.window_size_y, 640);
+        assert!(res.integer_scale);
+        assert_eq!(res.clip_quirk, ClipQuirk::Wrap);
+    }
+
+    #[test]
+    fn new_from_args_rom_paths_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "pong.ch8".to_owned(), "-no_sound".to_owned(),
+                                     "tetris.ch8".to_owned(), "roms/brix.ch8".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.rom_paths, vec!["pong.ch8", "tetris.ch8", "roms/brix.ch8"]);
+        assert!(!res.sound_enabled);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "-test_pattern".to_owned(), "-scale:5".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().rom_paths.is_empty());
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "-".to_owned(), "-no_sound".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().rom_paths, vec!["-"]);
+    }
+
+    #[test]
+    fn new_from_args_audio_latency_test() {
+        let to_args = |audio_latency: &str| vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), audio_latency.to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new().audio_latency, None);
+        assert_eq!(ApplicationCmdSettings::new_from_args(&to_args("-audio_latency:512")).unwrap().audio_latency, Some(512));
+        assert_eq!(ApplicationCmdSettings::new_from_args(&to_args("-audio_latency:16")).unwrap().audio_latency, Some(16));
+
+        for value in ["500", "8", "16384", "abc"] {
+            let args = to_args(&format!("-audio_latency:{}", value));
+
+            assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+                arg: &args[2],
+                value,
+            }));
+        }
+    }
+
+    #[test]
+    fn new_from_args_clip_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-clip".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.clip_quirk, ClipQuirk::Clip);
+    }
+
+    #[test]
+    fn new_from_args_flicker_reduce_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-flicker_reduce".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().flicker_reduce);
+        assert!(ApplicationCmdSettings::new_from_config("flicker_reduce = true").unwrap().flicker_reduce);
+        assert!(!ApplicationCmdSettings::new().flicker_reduce);
+    }
+
+    #[test]
+    fn new_from_args_rom_offset_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-rom_offset:16".to_owned()];
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.rom_offset, 16);
+        assert_eq!(res.interpreter_config().rom_offset, 16);
+        assert_eq!(ApplicationCmdSettings::new().rom_offset, 0);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-rom_offset:-1".to_owned()];
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &"-rom_offset:-1".to_owned(),
+            value: "-1",
+        }));
+    }
+
+    #[test]
+    fn new_from_args_seed_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-seed:1234".to_owned()];
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.seed, Some(1234));
+        assert_eq!(res.interpreter_config().rng_seed, Some(1234));
+        assert_eq!(ApplicationCmdSettings::new().seed, None);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-seed:abc".to_owned()];
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &"-seed:abc".to_owned(),
+            value: "abc",
+        }));
+    }
+
+    #[test]
+    fn new_from_args_load_address_test() {
+        let to_args = |load_address: &str| vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), load_address.to_owned(),
+                                                 "-quirks:xochip".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new().load_address, 0x200);
+        assert_eq!(ApplicationCmdSettings::new_from_args(&to_args("-load_address:600")).unwrap().load_address, 0x600);
+        assert_eq!(ApplicationCmdSettings::new_from_args(&to_args("-load_address:0x600")).unwrap().load_address, 0x600);
+
+        // Only fits once -quirks:xochip grew the memory
+        assert_eq!(ApplicationCmdSettings::new_from_args(&to_args("-load_address:2000")).unwrap().load_address, 0x2000);
+
+        let args = to_args("-load_address:601");
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &args[2],
+            value: "601",
+        }));
+
+        let args = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-load_address:2000".to_owned()];
+        assert!(ApplicationCmdSettings::new_from_args(&args).is_err());
+
+        // Would overwrite the font
+        let args = to_args("-load_address:100");
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &args[2],
+            value: "100",
+        }));
+    }
+
+    #[test]
+    fn new_from_args_max_runtime_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-max_runtime:30".to_owned()];
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.max_runtime, 30);
+        assert_eq!(res.max_runtime_duration(), Some(Duration::from_secs(30)));
+
+        // Unlimited unless given
+        assert_eq!(ApplicationCmdSettings::new().max_runtime_duration(), None);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-max_runtime:-1".to_owned()];
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &"-max_runtime:-1".to_owned(),
+            value: "-1",
+        }));
+    }
+
+    #[test]
+    fn new_from_args_scanlines_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-scanlines".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().scanlines);
+        assert!(ApplicationCmdSettings::new_from_config("scanlines = true").unwrap().scanlines);
+        assert!(!ApplicationCmdSettings::new().scanlines);
+    }
+
+    #[test]
+    fn new_from_args_dim_decay_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-dim_decay:3".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().dim_decay, Some(3));
+        assert_eq!(ApplicationCmdSettings::new_from_config("dim_decay = 5").unwrap().dim_decay, Some(5));
+        assert_eq!(ApplicationCmdSettings::new().dim_decay, None);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-dim_decay".to_owned()];
+        assert!(ApplicationCmdSettings::new_from_args(&args).is_err());
+    }
+
+    #[test]
+    fn new_from_args_phosphor_test() {
+        let mut args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().phosphor_decay, None);
+
+        args.push("-phosphor".to_owned());
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().phosphor_decay, Some(DEFAULT_PHOSPHOR_DECAY));
+
+        args[2] = "-phosphor:20".to_owned();
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().phosphor_decay, Some(20));
+
+        args[2] = "-phosphor:300".to_owned();
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &"-phosphor:300".to_owned(),
+            value: "300",
+        }));
+    }
+
+    #[test]
+    fn new_from_args_fps_cap_test() {
+        let mut args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().fps_cap, 60);
+
+        args.push("-fps_cap:144".to_owned());
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().fps_cap, 144);
+
+        args[2] = "-fps_cap:0".to_owned();
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().fps_cap, 0);
+
+        args[2] = "-fps_cap".to_owned();
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionCount {
+            arg: &"-fps_cap".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn new_from_args_start_paused_test() {
+        let mut args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-no_sound".to_owned()];
+
+        assert!(!ApplicationCmdSettings::new_from_args(&args).unwrap().start_paused);
+
+        args.push("-start_paused".to_owned());
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().start_paused);
+    }
+
+    #[test]
+    fn new_from_args_replay_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-record:C:\\replays\\pong.txt".to_owned(),
+                                     "-playback:old.txt".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.record_path.as_deref(), Some("C:\\replays\\pong.txt"));
+        assert_eq!(res.playback_path.as_deref(), Some("old.txt"));
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-record".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args),
+                   Err(CommandLineArgError::InvalidArgumentOptionCount { arg: &"-record".to_owned() }));
+    }
+
+    #[test]
+    fn new_from_args_dump_memory_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(),
+                                     "-dump_memory_on_exit:C:\\dumps\\memory.bin".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.dump_memory_path.as_deref(), Some("C:\\dumps\\memory.bin"));
+    }
+
+    #[test]
+    fn new_from_args_trace_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-trace:trace.txt".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.trace_path.as_deref(), Some("trace.txt"));
+        assert!(res.interpreter_config().log_executions);
+        assert!(!ApplicationCmdSettings::new().interpreter_config().log_executions);
+    }
+
+    fn parse_keymap(keymap_arg: &str) -> Result<ApplicationCmdSettings, String> {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), keymap_arg.to_owned()];
+
+        ApplicationCmdSettings::new_from_args(&args).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn new_from_args_keymap_test() {
+        let res = parse_keymap("-keymap:X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V").unwrap();
+
+        assert_eq!(res.keymap.as_ref().map(|keymap| keymap.len()), Some(16));
+        assert_eq!(res.keymap.unwrap()[0xC], "4");
+
+        assert!(parse_keymap("-keymap:X,1,2,3").is_err());
+    }
+
+    #[test]
+    fn new_from_args_keymap_duplicate_test() {
+        assert_eq!(parse_keymap("-keymap:X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,x"),
+                   Err("Argument '-keymap:X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,x' maps key 'x' more than once".to_owned()));
+    }
+
+    #[test]
+    fn new_from_args_keymap_reserved_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(),
+                                     "-keymap:Escape,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::ReservedKeymapKey {
+            arg: &args[2],
+            key: "Escape",
+        }));
+
+        assert!(parse_keymap("-keymap:X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,p").is_err());
+
+        // The speed hotkeys take these too
+        for key in ["+", "Keypad +", "Keypad -"] {
+            let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(),
+                                         format!("-keymap:X,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,{}", key)];
+
+            assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::ReservedKeymapKey {
+                arg: &args[2],
+                key,
+            }));
+        }
+    }
+
+    #[test]
+    fn new_from_args_instructions_per_frame_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-ipf:15".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.instructions_per_frame, Some(15));
+        assert_eq!(res.cycles_per_frame(), 15);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-instructions_per_frame:7".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().cycles_per_frame(), 7);
+        assert_eq!(ApplicationCmdSettings::new().cycles_per_frame(), 10);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-clock_speed:900".to_owned(),
+                                     "-ipf:15".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::MutuallyExclusiveArguments {
+            arg: &"-ipf:15".to_owned(),
+            other: &"-clock_speed:900".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn next_frame_cycles_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-clock_speed:700".to_owned()];
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        let mut remainder = 0;
+        let cycles: Vec<u32> = (0..60).map(|_| res.next_frame_cycles(&mut remainder)).collect();
+
+        assert_eq!(cycles.iter().sum::<u32>(), 700);
+        assert!(cycles.iter().all(|frame_cycles| *frame_cycles == 11 || *frame_cycles == 12));
+        assert_eq!(remainder, 0);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-ipf:7".to_owned()];
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert!((0..60).all(|_| res.next_frame_cycles(&mut remainder) == 7));
+    }
+
+    #[test]
+    fn new_from_args_scale_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-scale:15".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!((res.window_size_x, res.window_size_y), (960, 480));
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-scale:0".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &"-scale:0".to_owned(),
+            value: "0",
+        }));
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-window_size:800:600".to_owned(),
+                                     "-scale:10".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::MutuallyExclusiveArguments {
+            arg: &"-scale:10".to_owned(),
+            other: &"-window_size:800:600".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn new_from_args_plane_colors_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().plane_colors, DEFAULT_PLANE_COLORS);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(),
+                                     "-plane_colors:101010:F0E0D0:#ff8000:0000Aa".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().plane_colors,
+                   [(0x10, 0x10, 0x10), (0xF0, 0xE0, 0xD0), (0xFF, 0x80, 0x00), (0x00, 0x00, 0xAA)]);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(),
+                                     "-plane_colors:000000:FFFFFF:GG0000:0000FF".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &"-plane_colors:000000:FFFFFF:GG0000:0000FF".to_owned(),
+            value: "GG0000",
+        }));
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-plane_colors:000000:FFF".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).is_err());
+    }
+
+    #[test]
+    fn new_from_args_backend_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().backend, Backend::Sdl);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-backend:terminal".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().backend, Backend::Terminal);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-backend:opengl".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &"-backend:opengl".to_owned(),
+            value: "opengl",
+        }));
+    }
+
+    #[test]
+    fn new_from_args_validate_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-validate".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().validate_only);
+        assert!(!ApplicationCmdSettings::new().validate_only);
+    }
+
+    #[test]
+    fn new_from_args_info_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-info".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().info_only);
+        assert!(!ApplicationCmdSettings::new().info_only);
+    }
+
+    #[test]
+    fn new_from_args_ascii_dump_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-ascii_dump".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().ascii_dump);
+        assert!(!ApplicationCmdSettings::new().ascii_dump);
+    }
+
+    #[test]
+    fn new_from_args_identify_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-identify".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().identify_only);
+        assert!(!ApplicationCmdSettings::new().identify_only);
+    }
+
+    #[test]
+    fn new_from_args_quirks_report_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-quirks_report".to_owned()];
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().quirks_report, Some(1));
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-quirks_report:b".to_owned()];
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().quirks_report, Some(0xB));
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-quirks_report:10".to_owned()];
+        assert!(ApplicationCmdSettings::new_from_args(&args).is_err());
+    }
+
+    #[test]
+    fn new_from_args_lenient_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-lenient".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().lenient);
+        assert!(!ApplicationCmdSettings::new().lenient);
+    }
+
+    #[test]
+    fn new_from_args_warn_vf_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-warn_vf".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().interpreter_config().warn_vf);
+        assert!(ApplicationCmdSettings::new_from_config("warn_vf = true").unwrap().warn_vf);
+        assert!(!ApplicationCmdSettings::new().warn_vf);
+    }
+
+    #[test]
+    fn new_from_args_legacy_sys_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-legacy_sys".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().interpreter_config().legacy_sys);
+        assert!(ApplicationCmdSettings::new_from_config("legacy_sys = true").unwrap().legacy_sys);
+        assert!(!ApplicationCmdSettings::new().interpreter_config().legacy_sys);
+    }
+
+    #[test]
+    fn new_from_args_protect_interpreter_mem_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-protect_interpreter_mem".to_owned()];
+
+        assert!(ApplicationCmdSettings::new_from_args(&args).unwrap().interpreter_config().protect_interpreter_mem);
+        assert!(ApplicationCmdSettings::new_from_config("protect_interpreter_mem = true").unwrap().protect_interpreter_mem);
+        assert!(!ApplicationCmdSettings::new().interpreter_config().protect_interpreter_mem);
+    }
+
+    #[test]
+    fn new_from_args_profile_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-profile".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert!(res.profile);
+        assert!(res.interpreter_config().profile);
+        assert!(!ApplicationCmdSettings::new().profile);
+    }
+
+    #[test]
+    fn new_from_config_test() {
+        let config = "# comment\nwindow_size_x = 1280\nwindow_size_y = 640\n\nsound = false\nquirks = \"schip\"\nclock_speed = 900\n";
+
+        let res = ApplicationCmdSettings::new_from_config(config).unwrap();
+
+        assert_eq!(res.window_size_x, 1280);
+        assert_eq!(res.window_size_y, 640);
+        assert!(!res.sound_enabled);
+        assert_eq!(res.clip_quirk, ClipQuirk::Clip);
+        assert_eq!(res.cpu_clock_speed, 900);
+
+        assert!(ApplicationCmdSettings::new_from_config("clock_speed = fast").is_err());
+        assert!(ApplicationCmdSettings::new_from_config("colour = 1").is_err());
+        assert!(ApplicationCmdSettings::new_from_config("clock_speed").is_err());
+        assert!(ApplicationCmdSettings::new_from_config("clock_speed = 900\ninstructions_per_frame = 20").is_err());
+    }
+
+    #[test]
+    fn new_from_config_colors_test() {
+        let res = ApplicationCmdSettings::new_from_config("colors = \"101010:F0E0D0:#ff8000:0000Aa\"").unwrap();
+
+        assert_eq!(res.plane_colors, [(0x10, 0x10, 0x10), (0xF0, 0xE0, 0xD0), (0xFF, 0x80, 0x00), (0x00, 0x00, 0xAA)]);
+
+        assert!(ApplicationCmdSettings::new_from_config("colors = \"101010:F0E0D0\"").is_err());
+        assert!(ApplicationCmdSettings::new_from_config("colors = \"101010:F0E0D0:GG0000:0000FF\"").is_err());
+    }
+
+    #[test]
+    fn from_config_and_args_clock_precedence_test() {
+        let to_args = |arg: &str| vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), arg.to_owned()];
+
+        // -clock_speed on the command line beats instructions_per_frame from the file
+        let res = ApplicationCmdSettings::from_config_and_args(Some("instructions_per_frame = 20"),
+                                                               &to_args("-clock_speed:1200")).unwrap();
+
+        assert_eq!(res.instructions_per_frame, None);
+        assert_eq!(res.cycles_per_frame(), 20);
+
+        // And -ipf beats clock_speed from the file
+        let res = ApplicationCmdSettings::from_config_and_args(Some("clock_speed = 700"), &to_args("-ipf:15")).unwrap();
+        let mut remainder = 0;
+
+        assert_eq!(res.cpu_clock_speed, DEFAULT_CLOCK_SPEED);
+        assert!((0..60).all(|_| res.next_frame_cycles(&mut remainder) == 15));
+    }
+
+    #[test]
+    fn from_config_and_args_precedence_test() {
+        let config = "window_size_x = 1280\nclock_speed = 900\n";
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-clock_speed:1200".to_owned(),
+                                     "-config:calico.toml".to_owned()];
+
+        let res = ApplicationCmdSettings::from_config_and_args(Some(config), &args).unwrap();
+
+        // CLI beats the file
+        assert_eq!(res.cpu_clock_speed, 1200);
+        // File beats the built-in default
+        assert_eq!(res.window_size_x, 1280);
+        // Built-in default when neither sets it
+        assert_eq!(res.window_size_y, 320);
+
+        let res = ApplicationCmdSettings::from_config_and_args(None, &args).unwrap();
+
+        assert_eq!(res.window_size_x, 640);
+    }
+
+    fn parse_quirks(profile_arg: &str) -> ApplicationCmdSettings {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), profile_arg.to_owned()];
+
+        ApplicationCmdSettings::new_from_args(&args).unwrap()
+    }
+
+    #[test]
+    fn new_from_args_quirks_chip8_test() {
+        let res = parse_quirks("-quirks:chip8");
+
+        assert_eq!(res.shift_quirk, ShiftQuirk::ShiftVy);
+        assert_eq!(res.memory_quirk, MemoryQuirk::IncrementI);
+        assert_eq!(res.clip_quirk, ClipQuirk::Clip);
+        assert_eq!(res.wait_key_quirk, WaitKeyQuirk::OnRelease);
+        assert!(res.display_wait);
+        assert_eq!(res.interpreter_config().stack_limit, COSMAC_VIP_STACK_LIMIT);
+    }
+
+    #[test]
+    fn new_from_args_quirks_schip_test() {
+        let res = parse_quirks("-quirks:schip");
+
+        assert_eq!(res.shift_quirk, ShiftQuirk::ShiftVx);
+        assert_eq!(res.memory_quirk, MemoryQuirk::LeaveI);
+        assert_eq!(res.clip_quirk, ClipQuirk::Clip);
+        assert_eq!(res.wait_key_quirk, WaitKeyQuirk::OnPress);
+        assert!(!res.display_wait);
+        assert_eq!(res.stack_limit, DEFAULT_STACK_LIMIT);
+    }
+
+    #[test]
+    fn new_from_args_quirks_xochip_test() {
+        let res = parse_quirks("-quirks:xochip");
+
+        assert_eq!(res.shift_quirk, ShiftQuirk::ShiftVy);
+        assert_eq!(res.memory_quirk, MemoryQuirk::IncrementI);
+        assert_eq!(res.clip_quirk, ClipQuirk::Wrap);
+        assert!(!res.display_wait);
+        assert_eq!(res.memory_size, XO_CHIP_MEMORY_SIZE);
+    }
+
+    #[test]
+    fn new_from_args_wait_release_test() {
+        assert_eq!(ApplicationCmdSettings::new().wait_key_quirk, WaitKeyQuirk::OnPress);
+        assert_eq!(parse_quirks("-wait_release").wait_key_quirk, WaitKeyQuirk::OnRelease);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-wait_release".to_owned(),
+                                     "-quirks:schip".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().wait_key_quirk, WaitKeyQuirk::OnRelease);
+        assert_eq!(ApplicationCmdSettings::new_from_config("wait_release = true").unwrap().wait_key_quirk,
+                   WaitKeyQuirk::OnRelease);
+    }
+
+    #[test]
+    fn new_from_args_timing_test() {
+        let mut args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-timing:vip".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().interpreter_config().timing_mode, TimingMode::VipCycles);
+        assert_eq!(ApplicationCmdSettings::new().timing_mode, TimingMode::Instructions);
+        assert_eq!(ApplicationCmdSettings::new_from_config("timing = \"vip\"").unwrap().timing_mode, TimingMode::VipCycles);
+
+        args[2] = "-timing:fast".to_owned();
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &"-timing:fast".to_owned(),
+            value: "fast",
+        }));
+    }
+
+    #[test]
+    fn new_from_args_vip_draw_test() {
+        assert_eq!(ApplicationCmdSettings::new().vip_draw_quirk, VipDrawQuirk::Off);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-vip_draw".to_owned(),
+                                     "-quirks:schip".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().interpreter_config().vip_draw_quirk,
+                   VipDrawQuirk::WrapRightClipBottom);
+        assert_eq!(ApplicationCmdSettings::new_from_config("vip_draw = true").unwrap().vip_draw_quirk,
+                   VipDrawQuirk::WrapRightClipBottom);
+    }
+
+    #[test]
+    fn new_from_args_edge_keys_test() {
+        assert_eq!(ApplicationCmdSettings::new().key_trigger_quirk, KeyTriggerQuirk::Level);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-edge_keys".to_owned(),
+                                     "-quirks:chip8".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args).unwrap().interpreter_config().key_trigger_quirk,
+                   KeyTriggerQuirk::Edge);
+        assert_eq!(ApplicationCmdSettings::new_from_config("edge_keys = true").unwrap().key_trigger_quirk,
+                   KeyTriggerQuirk::Edge);
+    }
+
+    #[test]
+    fn new_from_args_quirks_override_test() {
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-clip".to_owned(),
+                                     "-quirks:xochip".to_owned()];
+
+        let res = ApplicationCmdSettings::new_from_args(&args).unwrap();
+
+        assert_eq!(res.clip_quirk, ClipQuirk::Clip);
+        assert_eq!(res.shift_quirk, ShiftQuirk::ShiftVy);
+
+        let args: Vec<String> = vec!["rusty-calico-c8".to_owned(), "rom.ch8".to_owned(), "-quirks:cosmac".to_owned()];
+
+        assert_eq!(ApplicationCmdSettings::new_from_args(&args), Err(CommandLineArgError::InvalidArgumentOptionParse {
+            arg: &"-quirks:cosmac".to_owned(),
+            value: "cosmac",
+        }));
     }
 
     #[test]
@@ -0,0 +1,140 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use rusty_calico_c8::Chip8Interpreter;
+
+// Size of one font dot in window pixels
+const DOT_SIZE: u32 = 2;
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+// One line per row of text:
+//   V0:00 V1:00 V2:00 V3:00
+//   ... (V4-VF)
+//   PC:0200 I:0000
+//   SP:0 DT:00 ST:00
+pub fn format_debug_state(interpreter: &Chip8Interpreter) -> Vec<String> {
+    let mut lines: Vec<String> = interpreter.registers()
+        .chunks(4)
+        .enumerate()
+        .map(|(row, registers)| {
+            registers.iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X}:{:02X}", row * 4 + i, value))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+
+    lines.push(format!("PC:{:04X} I:{:04X}", interpreter.pc(), interpreter.index_register()));
+    lines.push(format!("SP:{:X} DT:{:02X} ST:{:02X}", interpreter.stack_slice().len(),
+                       interpreter.delay_timer(), interpreter.sound_timer()));
+
+    lines
+}
+
+// 3x5 glyphs for the characters format_debug_state() uses, top row first and the
+// leftmost dot in bit 2
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b111, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b111, 0b100, 0b100],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        _ => [0; 5]
+    }
+}
+
+// Drawn straight onto the canvas after the game texture so the frame buffer itself is never touched
+pub fn draw(canvas: &mut Canvas<Window>, lines: &[String]) -> Result<(), String> {
+    let char_advance = (GLYPH_WIDTH + 1) * DOT_SIZE;
+    let line_advance = (GLYPH_HEIGHT + 2) * DOT_SIZE;
+
+    let longest_line = lines.iter().map(|line| line.len()).max().unwrap_or(0) as u32;
+
+    canvas.set_draw_color(Color::RGB(0, 0, 64));
+    canvas.fill_rect(Rect::new(0, 0, longest_line * char_advance + DOT_SIZE * 2,
+                               lines.len() as u32 * line_advance + DOT_SIZE * 2))?;
+
+    let mut dots = vec![];
+
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let origin_x = DOT_SIZE * 2 + column as u32 * char_advance;
+            let origin_y = DOT_SIZE * 2 + row as u32 * line_advance;
+
+            for (glyph_y, bits) in glyph(c).iter().enumerate() {
+                for glyph_x in 0..GLYPH_WIDTH {
+                    if bits & (0b100 >> glyph_x) != 0 {
+                        dots.push(Rect::new((origin_x + glyph_x * DOT_SIZE) as i32,
+                                            (origin_y + glyph_y as u32 * DOT_SIZE) as i32,
+                                            DOT_SIZE, DOT_SIZE));
+                    }
+                }
+            }
+        }
+    }
+
+    canvas.set_draw_color(Color::RGB(255, 255, 0));
+    canvas.fill_rects(&dots)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rusty_calico_c8::Chip8Config;
+
+    #[test]
+    fn test_format_debug_state() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 6A42, A123
+        interpreter.load_rom_bytes(&[0x6A, 0x42, 0xA1, 0x23]).unwrap();
+        interpreter.execute_next_instruction().unwrap();
+        interpreter.execute_next_instruction().unwrap();
+        interpreter.set_delay_timer(0x1F);
+
+        let lines = format_debug_state(&interpreter);
+
+        assert_eq!(lines, vec![
+            "V0:00 V1:00 V2:00 V3:00",
+            "V4:00 V5:00 V6:00 V7:00",
+            "V8:00 V9:00 VA:42 VB:00",
+            "VC:00 VD:00 VE:00 VF:00",
+            "PC:0204 I:0123",
+            "SP:0 DT:1F ST:00",
+        ]);
+    }
+
+    #[test]
+    fn test_glyphs_cover_debug_state() {
+        let interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        for line in format_debug_state(&interpreter) {
+            for c in line.chars().filter(|c| *c != ' ') {
+                assert_ne!(glyph(c), [0; 5], "missing glyph for '{}'", c);
+            }
+        }
+    }
+}
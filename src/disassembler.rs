@@ -0,0 +1,183 @@
+// Which platform introduced an opcode, used to guess what a ROM was written for
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip,
+    Unknown,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DecodedOpcode {
+    pub mnemonic: String,
+    pub platform: Platform,
+}
+
+// Mnemonics follow Cowgod's technical reference, SCHIP/XO-CHIP ones follow Octo
+pub fn decode(opcode: u16) -> DecodedOpcode {
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let nn = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+
+    let (mnemonic, platform) = match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => ("CLS".to_owned(), Platform::Chip8),
+            0x00EE => ("RET".to_owned(), Platform::Chip8),
+            0x00FB => ("SCR".to_owned(), Platform::Schip),
+            0x00FC => ("SCL".to_owned(), Platform::Schip),
+            0x00FD => ("EXIT".to_owned(), Platform::Schip),
+            0x00FE => ("LOW".to_owned(), Platform::Schip),
+            0x00FF => ("HIGH".to_owned(), Platform::Schip),
+            _ if opcode & 0xFFF0 == 0x00C0 => (format!("SCD {}", n), Platform::Schip),
+            _ if opcode & 0xFFF0 == 0x00D0 => (format!("SCU {}", n), Platform::XoChip),
+            _ => (format!("SYS {:#05x}", nnn), Platform::Chip8)
+        },
+
+        0x1000 => (format!("JP {:#05x}", nnn), Platform::Chip8),
+        0x2000 => (format!("CALL {:#05x}", nnn), Platform::Chip8),
+        0x3000 => (format!("SE V{:X}, {:#04x}", x, nn), Platform::Chip8),
+        0x4000 => (format!("SNE V{:X}, {:#04x}", x, nn), Platform::Chip8),
+
+        0x5000 => match n {
+            0x0 => (format!("SE V{:X}, V{:X}", x, y), Platform::Chip8),
+            0x2 => (format!("SAVE V{:X} - V{:X}", x, y), Platform::XoChip),
+            0x3 => (format!("LOAD V{:X} - V{:X}", x, y), Platform::XoChip),
+            _ => (format!("DW {:#06x}", opcode), Platform::Unknown)
+        },
+
+        0x6000 => (format!("LD V{:X}, {:#04x}", x, nn), Platform::Chip8),
+        0x7000 => (format!("ADD V{:X}, {:#04x}", x, nn), Platform::Chip8),
+
+        0x8000 => match n {
+            0x0 => (format!("LD V{:X}, V{:X}", x, y), Platform::Chip8),
+            0x1 => (format!("OR V{:X}, V{:X}", x, y), Platform::Chip8),
+            0x2 => (format!("AND V{:X}, V{:X}", x, y), Platform::Chip8),
+            0x3 => (format!("XOR V{:X}, V{:X}", x, y), Platform::Chip8),
+            0x4 => (format!("ADD V{:X}, V{:X}", x, y), Platform::Chip8),
+            0x5 => (format!("SUB V{:X}, V{:X}", x, y), Platform::Chip8),
+            0x6 => (format!("SHR V{:X}, V{:X}", x, y), Platform::Chip8),
+            0x7 => (format!("SUBN V{:X}, V{:X}", x, y), Platform::Chip8),
+            0xE => (format!("SHL V{:X}, V{:X}", x, y), Platform::Chip8),
+            _ => (format!("DW {:#06x}", opcode), Platform::Unknown)
+        },
+
+        0x9000 if n == 0 => (format!("SNE V{:X}, V{:X}", x, y), Platform::Chip8),
+        0xA000 => (format!("LD I, {:#05x}", nnn), Platform::Chip8),
+        0xB000 => (format!("JP V0, {:#05x}", nnn), Platform::Chip8),
+        0xC000 => (format!("RND V{:X}, {:#04x}", x, nn), Platform::Chip8),
+        0xD000 if n == 0 => (format!("DRW V{:X}, V{:X}, 0", x, y), Platform::Schip),
+        0xD000 => (format!("DRW V{:X}, V{:X}, {}", x, y, n), Platform::Chip8),
+
+        0xE000 => match nn {
+            0x9E => (format!("SKP V{:X}", x), Platform::Chip8),
+            0xA1 => (format!("SKNP V{:X}", x), Platform::Chip8),
+            _ => (format!("DW {:#06x}", opcode), Platform::Unknown)
+        },
+
+        0xF000 => match nn {
+            0x00 if x == 0 => ("LD I, long".to_owned(), Platform::XoChip),
+            0x01 => (format!("PLANE {}", x), Platform::XoChip),
+            0x02 if x == 0 => ("AUDIO".to_owned(), Platform::XoChip),
+            0x07 => (format!("LD V{:X}, DT", x), Platform::Chip8),
+            0x0A => (format!("LD V{:X}, K", x), Platform::Chip8),
+            0x15 => (format!("LD DT, V{:X}", x), Platform::Chip8),
+            0x18 => (format!("LD ST, V{:X}", x), Platform::Chip8),
+            0x1E => (format!("ADD I, V{:X}", x), Platform::Chip8),
+            0x29 => (format!("LD F, V{:X}", x), Platform::Chip8),
+            0x30 => (format!("LD HF, V{:X}", x), Platform::Schip),
+            0x33 => (format!("LD B, V{:X}", x), Platform::Chip8),
+            0x3A => (format!("PITCH V{:X}", x), Platform::XoChip),
+            0x55 => (format!("LD [I], V{:X}", x), Platform::Chip8),
+            0x65 => (format!("LD V{:X}, [I]", x), Platform::Chip8),
+            0x75 => (format!("LD R, V{:X}", x), Platform::Schip),
+            0x85 => (format!("LD V{:X}, R", x), Platform::Schip),
+            _ => (format!("DW {:#06x}", opcode), Platform::Unknown)
+        },
+
+        _ => (format!("DW {:#06x}", opcode), Platform::Unknown)
+    };
+
+    DecodedOpcode { mnemonic, platform }
+}
+
+// Basic facts about a ROM image, the platform guesses are a best effort scan since
+// data can't be told apart from code
+#[derive(Debug, PartialEq)]
+pub struct RomInfo {
+    pub size: usize,
+    pub words: usize,
+    pub uses_schip: bool,
+    pub uses_xochip: bool,
+    // (address, opcode, mnemonic)
+    pub first_instructions: Vec<(u16, u16, String)>,
+}
+
+impl RomInfo {
+    pub fn new(rom: &[u8], instruction_count: usize) -> RomInfo {
+        let mut uses_schip = false;
+        let mut uses_xochip = false;
+        let mut first_instructions = vec![];
+
+        for (i, word) in rom.chunks_exact(2).enumerate() {
+            let opcode = (word[0] as u16) << 8 | word[1] as u16;
+            let decoded = decode(opcode);
+
+            match decoded.platform {
+                Platform::Schip => uses_schip = true,
+                Platform::XoChip => uses_xochip = true,
+                _ => ()
+            }
+
+            if i < instruction_count {
+                first_instructions.push((0x200 + i as u16 * 2, opcode, decoded.mnemonic));
+            }
+        }
+
+        RomInfo {
+            size: rom.len(),
+            words: rom.len() / 2,
+            uses_schip,
+            uses_xochip,
+            first_instructions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(0x00E0), DecodedOpcode { mnemonic: "CLS".to_owned(), platform: Platform::Chip8 });
+        assert_eq!(decode(0x6A05).mnemonic, "LD VA, 0x05");
+        assert_eq!(decode(0xD125).mnemonic, "DRW V1, V2, 5");
+        assert_eq!(decode(0xD120).platform, Platform::Schip);
+        assert_eq!(decode(0x00C4).platform, Platform::Schip);
+        assert_eq!(decode(0xF000).platform, Platform::XoChip);
+        assert_eq!(decode(0x8128).platform, Platform::Unknown);
+    }
+
+    #[test]
+    fn test_rom_info_detects_schip() {
+        // 0x200: CLS, 0x202: HIGH, 0x204: jump to 0x204
+        let info = RomInfo::new(&[0x00, 0xE0, 0x00, 0xFF, 0x12, 0x04], 2);
+
+        assert_eq!(info.size, 6);
+        assert_eq!(info.words, 3);
+        assert!(info.uses_schip);
+        assert!(!info.uses_xochip);
+        assert_eq!(info.first_instructions, vec![(0x200, 0x00E0, "CLS".to_owned()), (0x202, 0x00FF, "HIGH".to_owned())]);
+    }
+
+    #[test]
+    fn test_rom_info_plain_chip8() {
+        let info = RomInfo::new(&[0x60, 0x05, 0xF0, 0x18, 0x12, 0x04], 8);
+
+        assert!(!info.uses_schip);
+        assert!(!info.uses_xochip);
+        assert_eq!(info.first_instructions.len(), 3);
+    }
+}
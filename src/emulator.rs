@@ -1,14 +1,46 @@
-use std::time::Duration;
-
-use sdl2::audio::AudioSpecDesired;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::PixelFormatEnum;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+
+use sdl2::audio::{AudioDevice, AudioSpecDesired};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
+use sdl2::Sdl;
+
+use rusty_calico_c8::{CalicoEvent, CalicoKey, Chip8Interpreter, FrameOutcome};
 
 use crate::ApplicationCmdSettings;
+use crate::audio;
 use crate::audio::SquareWave;
-use crate::interpreter::{CalicoEvent, CalicoKey, Chip8Interpreter};
+use crate::debug_overlay;
+use crate::emulator_error::EmulatorError;
+use crate::input_script;
+use crate::profile;
+use crate::replay::{Player, Recorder};
+use crate::rom_source;
+use crate::rpl_flags;
+use crate::screenshot;
+use crate::state_dump;
+use crate::trace_file;
+
+const EMULATION_FRAME_MS: f64 = 1000.0 / 60.0;
+const MAX_CATCH_UP_FRAMES: f64 = 4.0;
+const MIN_SPEED_MULTIPLIER: f32 = 0.25;
+const MAX_SPEED_MULTIPLIER: f32 = 4.0;
+// Applied on top of the speed multiplier while the turbo key is held
+const TURBO_MULTIPLIER: f32 = 4.0;
+const WINDOW_TITLE: &str = "Rusty-Calico-C8";
+// -dim_decay, brightness of a pixel that recently went off
+const DIM_INTENSITY: u8 = 96;
+// -ascii_dump prints at most this often, busy ROMs change the screen every frame
+const ASCII_DUMP_INTERVAL_FRAMES: u64 = 30;
+// -scanlines, pixels smaller than this on screen are drawn without a gap
+const SCANLINE_MIN_CELL_SIZE: u32 = 3;
 
 // TODO move away from SDL2 to some graphics library
 
@@ -20,7 +52,7 @@ pub struct Emulator {
 impl Emulator {
     pub(crate) fn new<'a>(parsed_args: ApplicationCmdSettings) -> Emulator {
         Emulator {
-            interpreter: Chip8Interpreter::new(parsed_args.sound_enabled),
+            interpreter: Chip8Interpreter::new(parsed_args.interpreter_config()),
             parsed_args,
         }
     }
@@ -35,10 +67,10 @@ impl Emulator {
 
     fn get_calico_key_from_sdl_keycode(key: Keycode) -> CalicoKey {
         match key {
-            Keycode::Kp1 => CalicoKey::Mk1,
-            Keycode::Kp2 => CalicoKey::Mk2,
-            Keycode::Kp3 => CalicoKey::Mk3,
-            Keycode::Kp4 => CalicoKey::Mk4,
+            Keycode::Num1 => CalicoKey::Mk1,
+            Keycode::Num2 => CalicoKey::Mk2,
+            Keycode::Num3 => CalicoKey::Mk3,
+            Keycode::Num4 => CalicoKey::Mk4,
             Keycode::Q => CalicoKey::Q,
             Keycode::W => CalicoKey::W,
             Keycode::E => CalicoKey::E,
@@ -56,34 +88,262 @@ impl Emulator {
         }
     }
 
-    pub fn run(&mut self, rom_path: &String) -> Result<(), String> {
-        self.interpreter.load_rom(rom_path)
-            .map_err(|e| e.to_string())?; // TODO fix error, add path
+    // + and - double or halve the speed, kept within MIN_SPEED_MULTIPLIER..=MAX_SPEED_MULTIPLIER
+    fn step_speed_multiplier(current: f32, faster: bool) -> f32 {
+        let next = if faster { current * 2.0 } else { current / 2.0 };
 
-        let sdl_context = sdl2::init()?;
-        let sdl_video = sdl_context.video()?;
-        let sdl_audio = sdl_context.audio()?;
-        let mut sdl_timer = sdl_context.timer()?;
+        next.clamp(MIN_SPEED_MULTIPLIER, MAX_SPEED_MULTIPLIER)
+    }
 
-        // Audio
+    // Instructions per rendered frame at 'fps' with the speed multiplier applied, on average since emulation itself
+    // still runs in 60hz slices (an uncapped 'fps' of 0 counts as 60)
+    fn instructions_per_frame(clock_speed: u64, fps: u32, multiplier: f32) -> f64 {
+        let fps = if fps == 0 { 60 } else { fps };
+
+        clock_speed as f64 * multiplier as f64 / fps as f64
+    }
+
+    fn effective_speed_multiplier(speed_multiplier: f32, turbo_held: bool) -> f32 {
+        if turbo_held {
+            speed_multiplier * TURBO_MULTIPLIER
+        } else {
+            speed_multiplier
+        }
+    }
+
+    // Lit pixels snap to full brightness, unlit ones fade out by 'decay' each frame
+    fn decay_pixel_intensity(current: u8, lit: bool, decay: u8) -> u8 {
+        if lit {
+            255
+        } else {
+            current.saturating_sub(decay)
+        }
+    }
+
+    // -dim_decay, a pixel that goes unlit is fully off 'dim_decay' frames later and dimmed until then.
+    // Returns the frames left
+    fn dim_pixel_frames(frames_left: u8, lit: bool, dim_decay: u8) -> u8 {
+        if lit {
+            dim_decay
+        } else {
+            frames_left.saturating_sub(1)
+        }
+    }
+
+    // Full brightness while lit, a single dim step while frames are left, then off
+    fn dim_pixel_intensity(frames_left: u8, lit: bool) -> u8 {
+        if lit {
+            255
+        } else if frames_left > 0 {
+            DIM_INTENSITY
+        } else {
+            0
+        }
+    }
+
+    // A pixel only goes dark once it was off for two frames in a row, hides sprites that get erased and
+    // redrawn on alternate frames
+    fn merge_frames(current: &[bool], previous: &[bool]) -> Vec<bool> {
+        current.iter()
+            .zip(previous)
+            .map(|(current, previous)| *current || *previous)
+            .collect()
+    }
+
+    // 0 intensity is 'off', 255 is 'on', anything in between is a phosphor fade between them
+    fn blend_color(off: (u8, u8, u8), on: (u8, u8, u8), intensity: u8) -> [u8; 3] {
+        let blend = |from: u8, to: u8| (from as i32 + (to as i32 - from as i32) * intensity as i32 / 255) as u8;
+
+        [blend(off.0, on.0), blend(off.1, on.1), blend(off.2, on.2)]
+    }
+
+    // Where the 'native_w' x 'native_h' image goes inside the window, integer scaling keeps pixels
+    // square by using the biggest whole multiple that fits and centering it
+    fn calculate_target_rect(window_w: u32, window_h: u32, native_w: u32, native_h: u32, integer_scale: bool) -> Rect {
+        let scale = (window_w / native_w).min(window_h / native_h);
+
+        // Window smaller than the native image can't be integer scaled, stretch it instead
+        if !integer_scale || scale == 0 {
+            return Rect::new(0, 0, window_w, window_h);
+        }
+
+        let target_w = native_w * scale;
+        let target_h = native_h * scale;
+
+        Rect::new((window_w as i32 - target_w as i32) / 2,
+                  (window_h as i32 - target_h as i32) / 2,
+                  target_w,
+                  target_h)
+    }
+
+    // Screen rect of every CHIP8 pixel inside 'target', row by row, each 'gap' smaller than its cell to leave
+    // a dark line between pixels. Cells too small for that get no gap
+    fn calculate_pixel_rects(target: Rect, native_w: u32, native_h: u32, gap: u32) -> Vec<Rect> {
+        // Stretched images don't divide evenly, so every cell gets its own bounds
+        let cell_start = |offset: i32, length: u32, native: u32, index: u32| offset + (length * index / native) as i32;
+
+        let cell_too_small = target.width() / native_w < SCANLINE_MIN_CELL_SIZE ||
+            target.height() / native_h < SCANLINE_MIN_CELL_SIZE;
+        let gap = if cell_too_small { 0 } else { gap };
+
+        let mut rects = Vec::with_capacity((native_w * native_h) as usize);
 
+        for y in 0..native_h {
+            let top = cell_start(target.y(), target.height(), native_h, y);
+            let bottom = cell_start(target.y(), target.height(), native_h, y + 1);
+
+            for x in 0..native_w {
+                let left = cell_start(target.x(), target.width(), native_w, x);
+                let right = cell_start(target.x(), target.width(), native_w, x + 1);
+
+                rects.push(Rect::new(left, top, (right - left) as u32 - gap, (bottom - top) as u32 - gap));
+            }
+        }
+
+        rects
+    }
+
+    // File name without directories and extension, 'roms/pong.ch8' => 'pong'
+    fn rom_name(rom_path: &str) -> String {
+        Path::new(rom_path).file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| rom_path.to_owned())
+    }
+
+    // None = the test pattern is showing
+    fn window_title(rom_path: Option<&str>) -> String {
+        match rom_path {
+            Some(rom_path) => format!("{} \u{2014} {}", WINDOW_TITLE, Emulator::rom_name(rom_path)),
+            None => WINDOW_TITLE.to_owned()
+        }
+    }
+
+    // How long to sleep after a frame that took 'elapsed_ticks' of the performance counter, 0 once the frame ran
+    // over its budget
+    fn frame_delay_ms(elapsed_ticks: u64, frequency: u64, fps_cap: u32) -> u32 {
+        let elapsed_ms = elapsed_ticks as f64 * 1000.0 / frequency as f64;
+        let frame_budget_ms = 1000.0 / fps_cap as f64;
+
+        (frame_budget_ms - elapsed_ms).max(0.0).floor() as u32
+    }
+
+    // Next or previous entry of a 'len' long playlist, wrapping around at both ends
+    fn playlist_index(current: usize, len: usize, forward: bool) -> usize {
+        if forward {
+            (current + 1) % len
+        } else {
+            (current + len - 1) % len
+        }
+    }
+
+    // Starts over with a fresh interpreter running the first ROM from 'start' on that loads, moving in the
+    // given direction. With a playlist a broken ROM is only reported and skipped, returns the loaded index
+    fn load_playlist_entry(&mut self, start: usize, forward: bool) -> Result<usize, EmulatorError> {
+        let rom_paths = &self.parsed_args.rom_paths;
+        let mut index = start;
+
+        for _ in 0..rom_paths.len() {
+            let mut interpreter = Chip8Interpreter::new(self.parsed_args.interpreter_config());
+            // Survive switching ROMs like they survived a reset on the HP48
+            interpreter.set_rpl_flags(self.interpreter.rpl_flags());
+
+            match rom_source::load(&mut interpreter, &rom_paths[index]) {
+                Ok(_) => {
+                    self.interpreter = interpreter;
+
+                    return Ok(index);
+                }
+
+                Err(e) if rom_paths.len() == 1 => {
+                    return Err(EmulatorError::RomLoad(format!("Unable to load '{}': {}", rom_paths[index], e)));
+                }
+
+                Err(e) => println!("Unable to load '{}': {}, skipping", rom_paths[index], e)
+            }
+
+            index = Emulator::playlist_index(index, rom_paths.len(), forward);
+        }
+
+        Err(EmulatorError::RomLoad("None of the ROMs could be loaded".to_owned()))
+    }
+
+    // The device keeps running, the callback stays silent while 'remaining_samples' is zero
+    fn open_audio(sdl_context: &Sdl, audio_latency: Option<u16>,
+                  remaining_samples: Arc<AtomicU32>) -> Option<AudioDevice<SquareWave>> {
         let desired_spec = AudioSpecDesired {
             freq: Some(44100),
-            channels: Some(1),  // mono
-            samples: None,       // default sample size
+            channels: Some(1),      // mono
+            samples: audio_latency, // None = default sample size
+        };
+
+        let opened = sdl_context.audio().and_then(|sdl_audio| {
+            sdl_audio.open_playback(None, &desired_spec, |spec| {
+                SquareWave::new(440.0 / spec.freq as f32, 0.0, 0.25, remaining_samples)
+            })
+        });
+
+        let audio_device = Emulator::audio_or_silence(opened);
+
+        if let Some(audio_device) = &audio_device {
+            audio_device.resume();
+        }
+
+        audio_device
+    }
+
+    // Headless machines often have no audio device, that shouldn't stop the ROM from running
+    fn audio_or_silence<T>(opened: Result<T, String>) -> Option<T> {
+        match opened {
+            Ok(device) => Some(device),
+            Err(e) => {
+                eprintln!("Unable to open an audio device, continuing without sound: {}", e);
+
+                None
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), EmulatorError> {
+        let res = self.run_window();
+
+        state_dump::dump_on_error(self.parsed_args.dump_state_path.as_deref(), &self.interpreter, &res);
+
+        res
+    }
+
+    // No ROM paths shows the test pattern instead
+    fn run_window(&mut self) -> Result<(), EmulatorError> {
+        let mut playlist_position = if self.parsed_args.rom_paths.is_empty() {
+            self.interpreter.load_test_pattern();
+
+            0
+        } else {
+            self.load_playlist_entry(0, true)?
         };
 
-        let audio_device = sdl_audio.open_playback(None, &desired_spec, |spec| {
-            SquareWave::new(440.0 / spec.freq as f32, 0.0, 0.25)
-        })?;
+        if let Some(path) = &self.parsed_args.rpl_flags_path {
+            rpl_flags::load(path, &mut self.interpreter)?;
+        }
+
+        let sdl_context = sdl2::init()?;
+        let sdl_video = sdl_context.video()?;
+        let mut sdl_timer = sdl_context.timer()?;
+
+        // Audio
+
+        let remaining_samples = Arc::new(AtomicU32::new(0));
+        let audio_device = Emulator::open_audio(&sdl_context, self.parsed_args.audio_latency, remaining_samples.clone());
 
         // Graphics
 
+        let window_title = Emulator::window_title(self.parsed_args.rom_paths.get(playlist_position).map(|path| path.as_str()));
+
         let window = sdl_video
-            .window("Rusty-Calico-C8",
+            .window(&window_title,
                     self.parsed_args.window_size_x,
                     self.parsed_args.window_size_y)
             .position_centered()
+            .resizable()
             .build()
             .map_err(|e| e.to_string())?;
 
@@ -92,16 +352,118 @@ impl Emulator {
             .build()
             .map_err(|e| e.to_string())?;
 
+        // Recreated at the new size whenever the ROM switches resolution
+        let (mut width, mut height) = self.interpreter.frame_buffer.resolution();
+
         let texture_creator = canvas.texture_creator();
         let mut texture = texture_creator
-            .create_texture_streaming(PixelFormatEnum::RGB24, 64, 32)
+            .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
             .map_err(|e| e.to_string())?;
 
         let mut event_pump = sdl_context.event_pump()?;
 
+        // Only used in phosphor and dim mode, 255 = fully lit, 0 = background
+        let mut pixel_intensities = vec![0u8; width as usize * height as usize];
+        // Only used with -dim_decay, frames until each unlit pixel is fully off
+        let mut dim_frames_left = vec![0u8; width as usize * height as usize];
+        // Only used with -flicker_reduce, what the frame buffer held on the previous frame
+        let mut previous_frame = vec![false; width as usize * height as usize];
+
+        // Starts at the configured size, follows the window when it gets resized
+        let mut window_size = (self.parsed_args.window_size_x, self.parsed_args.window_size_y);
+        let plane_colors = self.parsed_args.plane_colors;
+
+        // Set when the whole window has to be presented again even if the frame buffer didn't change
+        let mut force_redraw = false;
+        let mut debug_overlay_visible = false;
+
+        let mut paused = self.parsed_args.start_paused;
+        let mut step_requested = false;
+        // Scales how fast emulated time passes, so instructions and timers speed up or slow down together
+        let mut speed_multiplier: f32 = 1.0;
+        let mut turbo_held = false;
+
+        // Replays
+
+        let player = match &self.parsed_args.playback_path {
+            Some(path) => {
+                let file = File::open(path)
+                    .map_err(|e| format!("Unable to open replay '{}': {}", path, e))?;
+
+                Some(Player::new(BufReader::new(file))
+                    .map_err(|e| format!("Unable to read replay '{}': {}", path, e))?)
+            }
+            None => None
+        };
+
+        let input_script = match &self.parsed_args.input_script_path {
+            Some(path) => Some(input_script::open(path)?),
+            None => None
+        };
+
+        // Seed is always known so a recording can be replayed exactly, a replay brings its own
+        let rng_seed = match (&player, self.parsed_args.seed) {
+            (Some(player), _) => player.seed(),
+            (None, Some(seed)) => seed,
+            (None, None) => rand::random()
+        };
+
+        self.interpreter.seed_rng(rng_seed);
+
+        let mut recorder = match &self.parsed_args.record_path {
+            Some(path) => {
+                let file = File::create(path)
+                    .map_err(|e| format!("Unable to create replay '{}': {}", path, e))?;
+
+                Some(Recorder::new(BufWriter::new(file), rng_seed)
+                    .map_err(|e| e.to_string())?)
+            }
+            None => None
+        };
+
+        let mut trace_writer = match &self.parsed_args.trace_path {
+            Some(path) => Some(trace_file::create(path)?),
+            None => None
+        };
+
+        let keymap = match &self.parsed_args.keymap {
+            Some(names) => Some(names.iter()
+                .map(|name| Keycode::from_name(name).ok_or(format!("Unknown key '{}' in keymap", name)))
+                .collect::<Result<Vec<_>, _>>()?),
+            None => None
+        };
+
+        let mut frame_count: u64 = 0;
+        // Fraction of an instruction the clock speed owes, see next_frame_cycles()
+        let mut cycle_remainder = 0;
+
+        // Emulation is stepped in 60hz slices of real time, so timers keep their speed whatever the FPS cap is
+        let mut last_timer = sdl_timer.performance_counter();
+        let mut emulation_time_ms = EMULATION_FRAME_MS;
+
+        let run_start = Instant::now();
+
+        // -ascii_dump, whether the screen changed since the last dump and the frame that dump happened on
+        let mut ascii_dump_pending = false;
+        let mut last_ascii_dump_frame = None;
+
         'running: loop {
+            if self.parsed_args.max_runtime_duration().is_some_and(|max_runtime| run_start.elapsed() >= max_runtime) {
+                break 'running;
+            }
+
             let start_timer = sdl_timer.performance_counter();
 
+            let multiplier = Emulator::effective_speed_multiplier(speed_multiplier, turbo_held) as f64;
+
+            emulation_time_ms += (start_timer - last_timer) as f64 * 1000.0 / sdl_timer.performance_frequency() as f64
+                * multiplier;
+            last_timer = start_timer;
+
+            // Don't try to catch up after a long stall (window dragged, debugger attached), also drops
+            // the turbo backlog as soon as the key is released
+            emulation_time_ms = emulation_time_ms.min(EMULATION_FRAME_MS * MAX_CATCH_UP_FRAMES * multiplier);
+
             for event in event_pump.poll_iter() {
                 match event {
                     Event::Quit { .. } | Event::KeyDown {
@@ -109,9 +471,121 @@ impl Emulator {
                         ..
                     } => break 'running,
 
+                    // Mute is an emulator hotkey, not a CHIP8 key, so it never reaches handle_event
+                    Event::KeyDown {
+                        keycode: Some(Keycode::M),
+                        repeat: false,
+                        ..
+                    } => self.interpreter.toggle_sound(),
+
+                    Event::KeyDown {
+                        keycode: Some(keycode @ (Keycode::Equals | Keycode::Plus | Keycode::KpPlus |
+                                                 Keycode::Minus | Keycode::KpMinus)),
+                        repeat: false,
+                        ..
+                    } => {
+                        let faster = matches!(keycode, Keycode::Equals | Keycode::Plus | Keycode::KpPlus);
+
+                        speed_multiplier = Emulator::step_speed_multiplier(speed_multiplier, faster);
+
+                        let clock_speed = self.parsed_args.instructions_per_frame
+                            .map_or(self.parsed_args.cpu_clock_speed, |instructions| instructions as u64 * 60);
+
+                        println!("Speed {}x, {:.0} instructions per frame", speed_multiplier,
+                                 Emulator::instructions_per_frame(clock_speed, self.parsed_args.fps_cap, speed_multiplier));
+                    }
+
+                    // Fast forwards only while held
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Backquote),
+                        ..
+                    } => turbo_held = true,
+
+                    Event::KeyUp {
+                        keycode: Some(Keycode::Backquote),
+                        ..
+                    } => turbo_held = false,
+
+                    Event::KeyDown {
+                        keycode: Some(Keycode::P),
+                        repeat: false,
+                        ..
+                    } => paused = !paused,
+
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F12),
+                        repeat: false,
+                        ..
+                    } => {
+                        match screenshot::save_screenshot(&self.interpreter.frame_buffer,
+                                                          [plane_colors[1].0, plane_colors[1].1, plane_colors[1].2],
+                                                          [plane_colors[0].0, plane_colors[0].1, plane_colors[0].2]) {
+                            Ok(file_name) => println!("Saved screenshot to '{}'", file_name),
+                            Err(e) => println!("Unable to save screenshot: {}", e)
+                        }
+                    }
+
+                    // Tab goes to the next ROM of the playlist, Shift+Tab back to the previous one
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Tab),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } if self.parsed_args.rom_paths.len() > 1 => {
+                        let forward = !keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                        let next = Emulator::playlist_index(playlist_position, self.parsed_args.rom_paths.len(), forward);
+
+                        playlist_position = self.load_playlist_entry(next, forward)?;
+
+                        canvas.window_mut()
+                            .set_title(&Emulator::window_title(Some(&self.parsed_args.rom_paths[playlist_position])))
+                            .map_err(|e| e.to_string())?;
+
+                        self.interpreter.seed_rng(rng_seed);
+                        force_redraw = true;
+                    }
+
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F3),
+                        repeat: false,
+                        ..
+                    } => {
+                        debug_overlay_visible = !debug_overlay_visible;
+                        force_redraw = true;
+                    }
+
+                    // Single step only makes sense while paused
+                    Event::KeyDown {
+                        keycode: Some(Keycode::N),
+                        ..
+                    } => step_requested = paused,
+
+                    Event::Window {
+                        win_event: WindowEvent::Resized(w, h) | WindowEvent::SizeChanged(w, h),
+                        ..
+                    } => {
+                        window_size = (w.max(0) as u32, h.max(0) as u32);
+                        force_redraw = true;
+                    }
+
+                    // Keys held while switching away never get their KeyUp
+                    Event::Window {
+                        win_event: WindowEvent::FocusLost,
+                        ..
+                    } if player.is_none() => self.interpreter.clear_keys(),
+
+                    // Played back input replaces the keyboard
                     Event::KeyDown { keycode, .. } |
-                    Event::KeyUp { keycode, .. } => {
+                    Event::KeyUp { keycode, .. } if player.is_none() => {
                         match keycode {
+                            Some(key) if keymap.is_some() => {
+                                let pressed = matches!(event, Event::KeyDown { .. });
+
+                                if let Some(hex_key) = keymap.as_ref().and_then(|keymap| keymap.iter().position(|k| *k == key)) {
+                                    self.interpreter.set_key(hex_key as u8, pressed);
+                                }
+                            }
+
                             Some(key) => {
                                 self.interpreter.handle_event(Emulator::get_calico_event_from_sdl_event(event),
                                                               Emulator::get_calico_key_from_sdl_keycode(key));
@@ -124,50 +598,402 @@ impl Emulator {
                 }
             }
 
-            for _ in 0..self.parsed_args.cpu_clock_speed / 60 {
-                self.interpreter.execute_next_instruction()
+            let frame = if paused {
+                // Otherwise the time spent paused would be fast forwarded through on resume
+                emulation_time_ms = 0.0;
+
+                if step_requested {
+                    self.interpreter.execute_next_instruction()?;
+
+                    step_requested = false;
+                }
+
+                FrameOutcome { redraw: self.interpreter.take_draw_flag(), play_sound: false }
+            } else {
+                let mut frame = FrameOutcome { redraw: false, play_sound: false };
+
+                while emulation_time_ms >= EMULATION_FRAME_MS {
+                    if let Some(player) = &player {
+                        self.interpreter.set_keypad_state(player.keypad_state(frame_count));
+                    }
+
+                    if let Some(input_script) = &input_script {
+                        for event in input_script.events_at(frame_count) {
+                            self.interpreter.set_key(event.hex_key, event.pressed);
+                        }
+                    }
+
+                    if let Some(recorder) = &mut recorder {
+                        recorder.record_frame(frame_count, self.interpreter.keypad_state())
+                            .map_err(|e| e.to_string())?;
+                    }
+
+                    frame_count += 1;
+                    emulation_time_ms -= EMULATION_FRAME_MS;
+
+                    let outcome = self.interpreter.run_frame(self.parsed_args.next_frame_cycles(&mut cycle_remainder))?;
+
+                    frame.redraw |= outcome.redraw;
+                    frame.play_sound = outcome.play_sound;
+                }
+
+                frame
+            };
+
+            if let Some(trace_writer) = &mut trace_writer {
+                trace_writer.write_instructions(self.interpreter.drain_execution_log())
+                    .map_err(|e| format!("Unable to write trace: {}", e))?;
+            }
+
+            if self.parsed_args.ascii_dump {
+                ascii_dump_pending |= frame.redraw;
+
+                let interval_passed = last_ascii_dump_frame
+                    .is_none_or(|last_frame| frame_count >= last_frame + ASCII_DUMP_INTERVAL_FRAMES);
+
+                if ascii_dump_pending && interval_passed {
+                    println!("{}", self.interpreter.frame_buffer.to_ascii());
+
+                    ascii_dump_pending = false;
+                    last_ascii_dump_frame = Some(frame_count);
+                }
+            }
+
+            // Refreshed every iteration from the sound timer, whether or not an emulated frame ran in it, the
+            // callback counts it down to the exact sample
+            if let Some(audio_device) = &audio_device {
+                let samples = if !paused && self.interpreter.should_play_sound() {
+                    audio::remaining_samples(self.interpreter.sound_timer(), audio_device.spec().freq)
+                } else {
+                    0
+                };
+
+                remaining_samples.store(samples, Ordering::Release);
+            }
+
+            // A playlist switch gets a fresh interpreter, so compare instead of only trusting the flag
+            if self.interpreter.take_resolution_changed() || self.interpreter.frame_buffer.resolution() != (width, height) {
+                (width, height) = self.interpreter.frame_buffer.resolution();
+
+                texture = texture_creator
+                    .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
                     .map_err(|e| e.to_string())?;
+                pixel_intensities = vec![0u8; width as usize * height as usize];
+                dim_frames_left = vec![0u8; width as usize * height as usize];
+                previous_frame = vec![false; width as usize * height as usize];
+                force_redraw = true;
             }
 
-            self.interpreter.tick_timers();
+            let (row_width, row_count) = (width as usize, height as usize);
 
-            if self.interpreter.should_play_sound() {
-                audio_device.resume();
-                std::thread::sleep(Duration::from_millis(10));
-                audio_device.pause();
+            // Fading pixels change every frame, so phosphor mode can't rely on the draw flag
+            if let Some(decay) = self.parsed_args.phosphor_decay {
+                for y in 0..row_count {
+                    for x in 0..row_width {
+                        let index = y * row_width + x;
+                        let pixel_state = self.interpreter.frame_buffer.pixel_value(x as u8, y as u8) != 0;
+
+                        pixel_intensities[index] = Emulator::decay_pixel_intensity(pixel_intensities[index],
+                                                                                   pixel_state, decay);
+                    }
+                }
             }
 
-            if self.interpreter.draw_flag {
-                texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-                    for y in 0..32 {
-                        for x in 0..64 {
-                            let offset = y * pitch + x * 3;
-                            let pixel_state = self.interpreter.frame_buffer.get_pixel(x as u8, y as u8);
+            let phosphor_enabled = self.parsed_args.phosphor_decay.is_some();
 
-                            buffer[offset] = if pixel_state { 255 } else { 0 };
-                            buffer[offset + 1] = if pixel_state { 255 } else { 0 };
-                            buffer[offset + 2] = if pixel_state { 255 } else { 0 };
-                        }
+            // Phosphor is the finer version of the same idea, so it wins when both are on
+            let dim_decay = self.parsed_args.dim_decay.filter(|_| !phosphor_enabled);
+
+            if let Some(dim_decay) = dim_decay {
+                for y in 0..row_count {
+                    for x in 0..row_width {
+                        let index = y * row_width + x;
+                        let pixel_state = self.interpreter.frame_buffer.pixel_value(x as u8, y as u8) != 0;
+
+                        dim_frames_left[index] = Emulator::dim_pixel_frames(dim_frames_left[index], pixel_state, dim_decay);
+                        pixel_intensities[index] = Emulator::dim_pixel_intensity(dim_frames_left[index], pixel_state);
+                    }
+                }
+            }
+
+            let intensities_enabled = phosphor_enabled || dim_decay.is_some();
+
+            // Fading and dimming already cover up flicker, so they win when both are on
+            let merged_frame = if self.parsed_args.flicker_reduce && !intensities_enabled {
+                let current_frame: Vec<_> = (0..row_count)
+                    .flat_map(|y| (0..row_width).map(move |x| (x, y)))
+                    .map(|(x, y)| self.interpreter.frame_buffer.pixel_value(x as u8, y as u8) != 0)
+                    .collect();
+
+                let merged_frame = Emulator::merge_frames(&current_frame, &previous_frame);
+                previous_frame = current_frame;
+
+                Some(merged_frame)
+            } else {
+                None
+            };
+
+            // All of them can change the picture without the frame buffer changing
+            let redraw_every_frame = intensities_enabled || merged_frame.is_some();
+
+            // Registers change nearly every frame, so the overlay keeps the window redrawing
+            if frame.redraw || redraw_every_frame || force_redraw || debug_overlay_visible {
+                // Only re-upload rows that changed since the last draw
+                for y in 0..row_count {
+                    if !redraw_every_frame && !force_redraw && !self.interpreter.frame_buffer.dirty_rows()[y] {
+                        continue;
+                    }
+
+                    let mut row = vec![0u8; row_width * 3];
+
+                    for x in 0..row_width {
+                        let offset = x * 3;
+                        let lit = match &merged_frame {
+                            Some(merged_frame) => merged_frame[y * row_width + x],
+                            None => self.interpreter.frame_buffer.pixel_value(x as u8, y as u8) != 0
+                        };
+
+                        let intensity = if intensities_enabled {
+                            pixel_intensities[y * row_width + x]
+                        } else if lit {
+                            255
+                        } else {
+                            0
+                        };
+
+                        // Fading pixels that are already off keep fading towards the plain on color
+                        let value = self.interpreter.frame_buffer.pixel_value(x as u8, y as u8);
+                        let on_color = plane_colors[value.max(1) as usize];
+
+                        row[offset..offset + 3].copy_from_slice(&Emulator::blend_color(plane_colors[0], on_color, intensity));
                     }
-                })?;
 
+                    texture.update(Rect::new(0, y as i32, width as u32, 1), &row, row_width * 3)
+                        .map_err(|e| e.to_string())?;
+                }
+
+                self.interpreter.frame_buffer.mark_clean();
+
+                let target_rect = Emulator::calculate_target_rect(window_size.0, window_size.1,
+                                                                  width as u32, height as u32,
+                                                                  self.parsed_args.integer_scale);
+
+                force_redraw = false;
+
+                // Also paints the letterbox border when integer scaling, and the gaps with -scanlines
+                canvas.set_draw_color(Color::RGB(0, 0, 0));
                 canvas.clear();
-                canvas.copy(&texture, None, Some(Rect::new(0, 0,
-                                                           self.parsed_args.window_size_x,
-                                                           self.parsed_args.window_size_y)))?;
+
+                if self.parsed_args.scanlines {
+                    let pixel_rects = Emulator::calculate_pixel_rects(target_rect, width as u32, height as u32, 1);
+
+                    for (index, pixel_rect) in pixel_rects.into_iter().enumerate() {
+                        let source = Rect::new((index % row_width) as i32, (index / row_width) as i32, 1, 1);
+
+                        canvas.copy(&texture, Some(source), Some(pixel_rect))?;
+                    }
+                } else {
+                    canvas.copy(&texture, None, Some(target_rect))?;
+                }
+
+                if debug_overlay_visible {
+                    debug_overlay::draw(&mut canvas, &debug_overlay::format_debug_state(&self.interpreter))?;
+                }
+
                 canvas.present();
+            }
 
-                self.interpreter.draw_flag = false;
+            // Uncapped, render as fast as possible
+            if self.parsed_args.fps_cap == 0 {
+                continue;
             }
 
             let end_timer = sdl_timer.performance_counter();
 
-            let elapsed_ms = (end_timer - start_timer) as f32 / (sdl_timer.performance_frequency() * 1000) as f32;
+            sdl_timer.delay(Emulator::frame_delay_ms(end_timer - start_timer, sdl_timer.performance_frequency(),
+                                                     self.parsed_args.fps_cap));
+        }
 
-            // Limit FPS to 60
-            sdl_timer.delay((16.666f32 - elapsed_ms).floor() as u32);
+        if self.parsed_args.profile {
+            for line in profile::format_opcode_counts(self.interpreter.opcode_counts()) {
+                println!("{}", line);
+            }
+        }
+
+        if let Some(trace_writer) = &mut trace_writer {
+            trace_writer.flush().map_err(|e| format!("Unable to write trace: {}", e))?;
+        }
+
+        if let Some(path) = &self.parsed_args.rpl_flags_path {
+            rpl_flags::save(path, &self.interpreter)?;
+        }
+
+        if let Some(path) = &self.parsed_args.dump_memory_path {
+            std::fs::write(path, self.interpreter.export_memory())
+                .map_err(|e| format!("Unable to write memory dump '{}': {}", path, e))?;
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_target_rect_stretch() {
+        assert_eq!(Emulator::calculate_target_rect(800, 500, 64, 32, false), Rect::new(0, 0, 800, 500));
+    }
+
+    #[test]
+    fn test_target_rect_integer_scale() {
+        assert_eq!(Emulator::calculate_target_rect(640, 320, 64, 32, true), Rect::new(0, 0, 640, 320));
+
+        // 800 / 64 = 12, 500 / 32 = 15 => scale 12 => 768 x 384
+        assert_eq!(Emulator::calculate_target_rect(800, 500, 64, 32, true), Rect::new(16, 58, 768, 384));
+    }
+
+    #[test]
+    fn test_target_rect_smaller_than_native() {
+        assert_eq!(Emulator::calculate_target_rect(50, 20, 64, 32, true), Rect::new(0, 0, 50, 20));
+        assert_eq!(Emulator::calculate_target_rect(640, 20, 64, 32, true), Rect::new(0, 0, 640, 20));
+        assert_eq!(Emulator::calculate_target_rect(50, 20, 64, 32, false), Rect::new(0, 0, 50, 20));
+    }
+
+    #[test]
+    fn test_frame_delay_ms() {
+        // 6ms of a 16.6ms frame at 60 FPS
+        assert_eq!(Emulator::frame_delay_ms(6_000, 1_000_000, 60), 10);
+        assert_eq!(Emulator::frame_delay_ms(0, 1_000_000, 30), 33);
+
+        // Ran over the budget, no delay instead of a huge one
+        assert_eq!(Emulator::frame_delay_ms(50_000, 1_000_000, 60), 0);
+    }
+
+    #[test]
+    fn test_calculate_pixel_rects() {
+        let rects = Emulator::calculate_pixel_rects(Rect::new(0, 0, 640, 320), 64, 32, 1);
+
+        assert_eq!(rects.len(), 64 * 32);
+        assert_eq!(rects[0], Rect::new(0, 0, 9, 9));
+        assert_eq!(rects[1], Rect::new(10, 0, 9, 9));
+        assert_eq!(rects[64], Rect::new(0, 10, 9, 9));
+
+        // Letterboxed at scale 4
+        let rects = Emulator::calculate_pixel_rects(Rect::new(16, 8, 256, 128), 64, 32, 1);
+        assert_eq!(rects[65], Rect::new(20, 12, 3, 3));
+
+        // Scale 1 and 2 have no room for a gap
+        let rects = Emulator::calculate_pixel_rects(Rect::new(0, 0, 64, 32), 64, 32, 1);
+        assert_eq!(rects[1], Rect::new(1, 0, 1, 1));
+
+        let rects = Emulator::calculate_pixel_rects(Rect::new(0, 0, 128, 64), 64, 32, 1);
+        assert_eq!(rects[1], Rect::new(2, 0, 2, 2));
+    }
+
+    #[test]
+    fn test_playlist_index() {
+        assert_eq!(Emulator::playlist_index(0, 3, true), 1);
+        assert_eq!(Emulator::playlist_index(2, 3, true), 0);
+        assert_eq!(Emulator::playlist_index(1, 3, false), 0);
+        assert_eq!(Emulator::playlist_index(0, 3, false), 2);
+        assert_eq!(Emulator::playlist_index(0, 1, false), 0);
+    }
+
+    #[test]
+    fn test_step_speed_multiplier() {
+        assert_eq!(Emulator::step_speed_multiplier(1.0, true), 2.0);
+        assert_eq!(Emulator::step_speed_multiplier(1.0, false), 0.5);
+        assert_eq!(Emulator::step_speed_multiplier(4.0, true), MAX_SPEED_MULTIPLIER);
+        assert_eq!(Emulator::step_speed_multiplier(0.25, false), MIN_SPEED_MULTIPLIER);
+        assert_eq!(Emulator::step_speed_multiplier(0.25, true), 0.5);
+    }
+
+    #[test]
+    fn test_instructions_per_frame() {
+        assert_eq!(Emulator::instructions_per_frame(600, 60, 1.0), 10.0);
+        assert_eq!(Emulator::instructions_per_frame(600, 60, 4.0), 40.0);
+        assert_eq!(Emulator::instructions_per_frame(600, 60, 0.25), 2.5);
+        assert_eq!(Emulator::instructions_per_frame(600, 120, 2.0), 10.0);
+        assert_eq!(Emulator::instructions_per_frame(600, 0, 1.0), 10.0);
+    }
+
+    #[test]
+    fn test_effective_speed_multiplier() {
+        assert_eq!(Emulator::effective_speed_multiplier(1.0, false), 1.0);
+        assert_eq!(Emulator::effective_speed_multiplier(1.0, true), TURBO_MULTIPLIER);
+        assert_eq!(Emulator::effective_speed_multiplier(0.5, true), 0.5 * TURBO_MULTIPLIER);
+        assert_eq!(Emulator::effective_speed_multiplier(0.5, false), 0.5);
+    }
+
+    #[test]
+    fn test_audio_or_silence() {
+        assert_eq!(Emulator::audio_or_silence(Ok(7)), Some(7));
+        assert_eq!(Emulator::audio_or_silence::<u32>(Err("No available audio device".to_owned())), None);
+    }
+
+    #[test]
+    fn test_blend_color() {
+        assert_eq!(Emulator::blend_color((0, 0, 0), (255, 255, 255), 0), [0, 0, 0]);
+        assert_eq!(Emulator::blend_color((0, 0, 0), (255, 255, 255), 255), [255, 255, 255]);
+        assert_eq!(Emulator::blend_color((200, 0, 100), (0, 200, 100), 255), [0, 200, 100]);
+        assert_eq!(Emulator::blend_color((200, 0, 100), (0, 200, 100), 51), [160, 40, 100]);
+    }
+
+    #[test]
+    fn test_decay_pixel_intensity() {
+        assert_eq!(Emulator::decay_pixel_intensity(0, true, 64), 255);
+        assert_eq!(Emulator::decay_pixel_intensity(100, true, 64), 255);
+        assert_eq!(Emulator::decay_pixel_intensity(255, false, 64), 191);
+        assert_eq!(Emulator::decay_pixel_intensity(30, false, 64), 0);
+        assert_eq!(Emulator::decay_pixel_intensity(255, false, 255), 0);
+    }
+
+    #[test]
+    fn test_window_title() {
+        assert_eq!(Emulator::window_title(Some("pong.ch8")), "Rusty-Calico-C8 \u{2014} pong");
+        assert_eq!(Emulator::window_title(Some("roms/schip/car.sc8")), "Rusty-Calico-C8 \u{2014} car");
+        assert_eq!(Emulator::window_title(Some("roms/Space Invaders")), "Rusty-Calico-C8 \u{2014} Space Invaders");
+        assert_eq!(Emulator::window_title(Some("roms/brix.ch8.gz")), "Rusty-Calico-C8 \u{2014} brix.ch8");
+        assert_eq!(Emulator::window_title(None), "Rusty-Calico-C8");
+    }
+
+    #[test]
+    fn test_dim_pixel_step() {
+        // Off the second frame after going unlit
+        let mut frames_left = 0;
+        let mut intensities = vec![];
+
+        for lit in [true, false, false, false, false] {
+            frames_left = Emulator::dim_pixel_frames(frames_left, lit, 2);
+            intensities.push(Emulator::dim_pixel_intensity(frames_left, lit));
+        }
+
+        assert_eq!(intensities, vec![255, DIM_INTENSITY, 0, 0, 0]);
+
+        // Relit before fading
+        assert_eq!(Emulator::dim_pixel_frames(1, true, 3), 3);
+        assert_eq!(Emulator::dim_pixel_intensity(0, false), 0);
+    }
+
+    #[test]
+    fn test_merge_frames() {
+        // Erased this frame but lit last frame stays lit, off in both goes dark
+        assert_eq!(Emulator::merge_frames(&[false, true, false, true], &[true, false, false, true]),
+                   vec![true, true, false, true]);
+
+        // Sprite moving one pixel right, the old position lingers for a frame
+        assert_eq!(Emulator::merge_frames(&[false, true, true, false], &[true, true, false, false]),
+                   vec![true, true, true, false]);
+
+        assert_eq!(Emulator::merge_frames(&[false; 4], &[false; 4]), vec![false; 4]);
+    }
+
+    #[test]
+    fn test_default_keys_use_top_row() {
+        assert_eq!(Emulator::get_calico_key_from_sdl_keycode(Keycode::Num4).to_hex(), Some(0xC));
+        assert_eq!(Emulator::get_calico_key_from_sdl_keycode(Keycode::W).to_hex(), Some(0x5));
+        assert_eq!(Emulator::get_calico_key_from_sdl_keycode(Keycode::V).to_hex(), Some(0xF));
+        assert_eq!(Emulator::get_calico_key_from_sdl_keycode(Keycode::Kp1), CalicoKey::Other);
+    }
+}
@@ -0,0 +1,38 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+
+use rusty_calico_c8::InterpreterError;
+
+// Why a frontend stopped, main turns the category into the exit code
+#[derive(Debug)]
+pub enum EmulatorError {
+    RomLoad(String),
+    Interpreter(InterpreterError),
+    // SDL, the terminal or some other file, not the ROM's fault
+    Frontend(String),
+}
+
+impl Display for EmulatorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::RomLoad(message) | EmulatorError::Frontend(message) => write!(f, "{}", message),
+            EmulatorError::Interpreter(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for EmulatorError {}
+
+// SDL and most of the frontend code report errors as plain strings
+impl From<String> for EmulatorError {
+    fn from(message: String) -> Self {
+        EmulatorError::Frontend(message)
+    }
+}
+
+impl From<InterpreterError> for EmulatorError {
+    fn from(error: InterpreterError) -> Self {
+        EmulatorError::Interpreter(error)
+    }
+}
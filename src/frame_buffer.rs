@@ -1,35 +1,254 @@
-pub struct FrameBuffer([bool; 64 * 32]);
+// SCHIP high resolution, lores uses the top left 64 x 32 of it
+const MAX_WIDTH: u8 = 128;
+const MAX_HEIGHT: usize = 64;
+
+// XO-CHIP bitplanes, a pixel's value (0-3) has one bit per plane
+pub const PLANE_COUNT: usize = 2;
+
+pub struct FrameBuffer {
+    // Per plane one u128 per row, x = 0 is the most significant bit of the current width, so lores only uses the
+    // low 64 bits
+    planes: [[u128; MAX_HEIGHT]; PLANE_COUNT],
+    // Bit mask of the planes drawing, clearing and scrolling work on, only the first one unless XO-CHIP's Fn01
+    // picks others
+    selected_planes: u8,
+    // Rows changed since the last mark_clean(), lets frontends upload only what changed
+    dirty_rows: [bool; MAX_HEIGHT],
+    hires: bool,
+}
 
 impl FrameBuffer {
     pub fn new() -> FrameBuffer {
-        FrameBuffer([false; 64 * 32])
+        FrameBuffer {
+            planes: [[0; MAX_HEIGHT]; PLANE_COUNT],
+            selected_planes: 0b01,
+            dirty_rows: [true; MAX_HEIGHT],
+            hires: false,
+        }
+    }
+
+    // (width, height), 64 x 32 or 128 x 64 in SCHIP high resolution
+    pub fn resolution(&self) -> (u8, u8) {
+        if self.hires {
+            (MAX_WIDTH, MAX_HEIGHT as u8)
+        } else {
+            (64, 32)
+        }
+    }
+
+    // Switching resolution clears the screen (every plane, whatever is selected) like Octo does. Returns whether
+    // the screen changed, either the resolution or a lit pixel
+    pub fn set_hires(&mut self, hires: bool) -> bool {
+        let resolution_changed = self.hires != hires;
+        self.hires = hires;
+
+        let changed = self.planes.iter().flatten().any(|row| *row != 0);
+        self.planes = [[0; MAX_HEIGHT]; PLANE_COUNT];
+
+        self.mark_all_dirty();
+
+        changed || resolution_changed
+    }
+
+    // Only the low PLANE_COUNT bits count, 0 selects no plane at all
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & ((1 << PLANE_COUNT) - 1);
     }
 
-    fn calculate_index_from_2d_cords(x: u8, y: u8, w: u8, h: u8) -> usize
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    // Indices of the selected planes, first plane first
+    pub fn selected_plane_indices(&self) -> impl Iterator<Item = usize> {
+        let selected_planes = self.selected_planes;
+
+        (0..PLANE_COUNT).filter(move |plane| selected_planes & (1 << plane) != 0)
+    }
+
+    fn height(&self) -> usize {
+        self.resolution().1 as usize
+    }
+
+    // Returns the row index and the bit mask of the pixel within that row
+    fn calculate_row_and_mask_from_2d_cords(&self, x: u8, y: u8) -> (usize, u128)
     {
+        let (width, height) = self.resolution();
+
         // "wrap around" cords
-        let x = x % w;
-        let y = y % h;
+        let x = x % width;
+        let y = y % height;
 
-        y as usize * w as usize + x as usize
+        (y as usize, 1 << (width - 1 - x))
     }
 
+    // The first plane, the only one plain CHIP8 and SCHIP ROMs draw to
     pub fn get_pixel(&self, x_cord: u8, y_cord: u8) -> bool {
-        let pixel_index = FrameBuffer::calculate_index_from_2d_cords(x_cord, y_cord, 64, 32);
-
-        self.0[pixel_index]
+        self.get_plane_pixel(0, x_cord, y_cord)
     }
 
     pub fn flip_pixel(&mut self, x_cord: u8, y_cord: u8) {
-        let pixel_index = FrameBuffer::calculate_index_from_2d_cords(x_cord, y_cord, 64, 32);
+        self.flip_plane_pixel(0, x_cord, y_cord);
+    }
+
+    pub fn get_plane_pixel(&self, plane: usize, x_cord: u8, y_cord: u8) -> bool {
+        let (row, mask) = self.calculate_row_and_mask_from_2d_cords(x_cord, y_cord);
+
+        self.planes[plane][row] & mask != 0
+    }
+
+    pub fn flip_plane_pixel(&mut self, plane: usize, x_cord: u8, y_cord: u8) {
+        let (row, mask) = self.calculate_row_and_mask_from_2d_cords(x_cord, y_cord);
+
+        self.planes[plane][row] ^= mask;
+        self.dirty_rows[row] = true;
+    }
+
+    // 0-3, bit 0 from the first plane and bit 1 from the second, indexes the plane colors
+    pub fn pixel_value(&self, x_cord: u8, y_cord: u8) -> u8 {
+        (0..PLANE_COUNT)
+            .filter(|plane| self.get_plane_pixel(*plane, x_cord, y_cord))
+            .fold(0, |value, plane| value | 1 << plane)
+    }
+
+    // Runs 'change' on the rows of every selected plane. Returns whether any pixel changed
+    fn change_selected_planes(&mut self, change: impl Fn(&mut [u128])) -> bool {
+        let height = self.height();
+        let old_planes = self.planes;
+
+        for plane in self.selected_plane_indices().collect::<Vec<_>>() {
+            change(&mut self.planes[plane][..height]);
+        }
+
+        self.mark_all_dirty();
+
+        self.planes != old_planes
+    }
+
+    // Clears the selected planes. Returns whether any pixel was lit, clearing an empty screen changes nothing
+    pub fn clear(&mut self) -> bool {
+        self.change_selected_planes(|rows| rows.fill(0))
+    }
+
+    // Moves the selected planes down by 'n' rows, rows scrolled in at the top are cleared. Returns whether any
+    // pixel changed
+    pub fn scroll_down(&mut self, n: usize) -> bool {
+        self.change_selected_planes(|rows| {
+            let n = n.min(rows.len());
+
+            rows.copy_within(0..rows.len() - n, n);
+            rows[..n].fill(0);
+        })
+    }
+
+    // Moves the selected planes up by 'n' rows, rows scrolled in at the bottom are cleared. Returns whether any
+    // pixel changed
+    pub fn scroll_up(&mut self, n: usize) -> bool {
+        self.change_selected_planes(|rows| {
+            let n = n.min(rows.len());
+            let height = rows.len();
 
-        self.0[pixel_index] = !self.0[pixel_index];
+            rows.copy_within(n..height, 0);
+            rows[height - n..].fill(0);
+        })
     }
 
-    pub fn clear(&mut self) {
-        for pixel in self.0.iter_mut() {
-            *pixel = false;
+    // Moves the selected planes right by 'n' pixels, columns scrolled in on the left are cleared. Returns whether
+    // any pixel changed
+    pub fn scroll_right(&mut self, n: usize) -> bool {
+        let n = n.min(self.resolution().0 as usize) as u32;
+
+        // x = 0 is the most significant bit, moving right shifts towards the least significant one
+        self.change_selected_planes(|rows| rows.iter_mut().for_each(|row| *row = row.checked_shr(n).unwrap_or(0)))
+    }
+
+    // Moves the selected planes left by 'n' pixels, columns scrolled in on the right are cleared. Returns whether
+    // any pixel changed
+    pub fn scroll_left(&mut self, n: usize) -> bool {
+        let width = self.resolution().0;
+        let n = n.min(width as usize) as u32;
+        let full_row = u128::MAX >> (MAX_WIDTH - width);
+
+        self.change_selected_planes(|rows| {
+            rows.iter_mut().for_each(|row| *row = row.checked_shl(n).unwrap_or(0) & full_row)
+        })
+    }
+
+    // Calibration image, a one pixel border around a checkerboard with (0, 0) lit
+    pub fn test_pattern(&mut self) {
+        let width = self.resolution().0;
+        let height = self.height();
+        let full_row = u128::MAX >> (MAX_WIDTH - width);
+
+        self.planes[1..].iter_mut().for_each(|rows| rows.fill(0));
+
+        for y in 0..height {
+            self.planes[0][y] = if y == 0 || y == height - 1 {
+                full_row
+            } else {
+                // Even rows (0xAAAA...) start lit on the left, odd ones (0x5555...) are shifted by a pixel,
+                // edges always lit
+                let checkerboard = if y % 2 == 0 { u128::MAX / 3 * 2 } else { u128::MAX / 3 };
+
+                (checkerboard & full_row) | 1 << (width - 1) | 1
+            };
+        }
+
+        self.mark_all_dirty();
+    }
+
+    // One line per row of the current resolution, '#' for pixels lit in any plane and ' ' for unlit ones
+    pub fn to_ascii(&self) -> String {
+        let (width, height) = self.resolution();
+        let mut res = String::with_capacity((width as usize + 1) * height as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                res.push(if self.pixel_value(x, y) != 0 { '#' } else { ' ' });
+            }
+
+            res.push('\n');
         }
+
+        res
+    }
+
+    // 64-bit FNV-1a over the rows as big endian bytes, top row first, 8 bytes per row in lores and 16 in
+    // hires. Meant for golden tests, so the algorithm and byte order must never change, a changed hash
+    // has to mean a changed screen. Later planes are only hashed once they have a lit pixel, so screens
+    // that only use the first plane keep their hash from before bitplanes existed
+    pub fn frame_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+        let row_bytes = if self.hires { 16 } else { 8 };
+        let height = self.height();
+
+        self.planes.iter()
+            .enumerate()
+            .filter(|(plane, rows)| *plane == 0 || rows[..height].iter().any(|row| *row != 0))
+            .flat_map(|(_, rows)| rows[..height].iter())
+            .flat_map(|row| row.to_be_bytes().into_iter().skip(16 - row_bytes))
+            .fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+    }
+
+    // One entry per row of the current resolution
+    pub fn dirty_rows(&self) -> &[bool] {
+        &self.dirty_rows[..self.height()]
+    }
+
+    pub fn mark_clean(&mut self) {
+        self.dirty_rows = [false; MAX_HEIGHT];
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty_rows = [true; MAX_HEIGHT];
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        FrameBuffer::new()
     }
 }
 
@@ -38,9 +257,293 @@ mod test {
     use super::*;
 
     #[test]
-    fn test_array_index_calculation() {
-        assert_eq!(0, FrameBuffer::calculate_index_from_2d_cords(0, 0, 10, 10));
-        assert_eq!(10, FrameBuffer::calculate_index_from_2d_cords(0, 1, 10, 10));
-        assert_eq!(57, FrameBuffer::calculate_index_from_2d_cords(7, 5, 10, 10))
+    fn test_row_and_mask_calculation() {
+        let frame_buffer = FrameBuffer::new();
+
+        assert_eq!((0, 1 << 63), frame_buffer.calculate_row_and_mask_from_2d_cords(0, 0));
+        assert_eq!((1, 1 << 63), frame_buffer.calculate_row_and_mask_from_2d_cords(0, 1));
+        assert_eq!((5, 1 << 56), frame_buffer.calculate_row_and_mask_from_2d_cords(7, 5));
+        assert_eq!((5, 1), frame_buffer.calculate_row_and_mask_from_2d_cords(63, 37));
+        assert_eq!((0, 1 << 63), frame_buffer.calculate_row_and_mask_from_2d_cords(64, 32))
+    }
+
+    #[test]
+    fn test_hires_row_and_mask_calculation() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.set_hires(true);
+
+        assert_eq!(frame_buffer.resolution(), (128, 64));
+        assert_eq!((0, 1 << 127), frame_buffer.calculate_row_and_mask_from_2d_cords(0, 0));
+        assert_eq!((40, 1 << 27), frame_buffer.calculate_row_and_mask_from_2d_cords(100, 40));
+        assert_eq!((0, 1 << 127), frame_buffer.calculate_row_and_mask_from_2d_cords(128, 64));
+    }
+
+    #[test]
+    fn test_set_hires_clears_screen() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.flip_pixel(3, 3);
+        frame_buffer.set_hires(true);
+        frame_buffer.flip_pixel(120, 60);
+
+        assert!(!frame_buffer.get_pixel(3, 3));
+        assert!(frame_buffer.get_pixel(120, 60));
+        assert_eq!(frame_buffer.dirty_rows().len(), 64);
+
+        frame_buffer.set_hires(false);
+
+        assert_eq!(frame_buffer.resolution(), (64, 32));
+        assert_eq!(frame_buffer.frame_hash(), FrameBuffer::new().frame_hash());
+    }
+
+    #[test]
+    fn test_pixels_across_row_boundaries() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.flip_pixel(63, 0);
+        frame_buffer.flip_pixel(0, 1);
+
+        assert!(frame_buffer.get_pixel(63, 0));
+        assert!(frame_buffer.get_pixel(0, 1));
+        assert!(!frame_buffer.get_pixel(0, 0));
+        assert!(!frame_buffer.get_pixel(62, 0));
+        assert!(!frame_buffer.get_pixel(1, 1));
+        assert!(!frame_buffer.get_pixel(63, 1));
+
+        // Flipping again turns the pixel back off without touching its neighbours
+        frame_buffer.flip_pixel(63, 0);
+
+        assert!(!frame_buffer.get_pixel(63, 0));
+        assert!(frame_buffer.get_pixel(0, 1));
+
+        frame_buffer.flip_pixel(63, 31);
+        frame_buffer.clear();
+
+        assert!(!frame_buffer.get_pixel(0, 1));
+        assert!(!frame_buffer.get_pixel(63, 31));
+    }
+
+    #[test]
+    fn test_test_pattern() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.mark_clean();
+        frame_buffer.test_pattern();
+
+        // Corners and edges belong to the border
+        assert!(frame_buffer.get_pixel(0, 0));
+        assert!(frame_buffer.get_pixel(63, 0));
+        assert!(frame_buffer.get_pixel(0, 31));
+        assert!(frame_buffer.get_pixel(63, 31));
+        assert!(frame_buffer.get_pixel(0, 15));
+        assert!(frame_buffer.get_pixel(63, 16));
+
+        // Checkerboard inside
+        assert!(frame_buffer.get_pixel(2, 2));
+        assert!(!frame_buffer.get_pixel(1, 2));
+        assert!(frame_buffer.get_pixel(1, 1));
+        assert!(!frame_buffer.get_pixel(2, 1));
+
+        assert!(frame_buffer.dirty_rows().iter().all(|dirty| *dirty));
+    }
+
+    #[test]
+    fn test_frame_hash_matches_for_same_pixels() {
+        let mut first = FrameBuffer::new();
+        let mut second = FrameBuffer::new();
+
+        first.flip_pixel(10, 5);
+        first.flip_pixel(63, 31);
+        second.flip_pixel(63, 31);
+        second.flip_pixel(10, 5);
+
+        // Dirty rows aren't part of the picture
+        second.mark_clean();
+
+        assert_eq!(first.frame_hash(), second.frame_hash());
+
+        // Pinned, the hash has to stay the same across releases
+        assert_eq!(FrameBuffer::new().frame_hash(), 0xD80A_C658_736B_B725);
+    }
+
+    #[test]
+    fn test_frame_hash_changes_when_pixel_flips() {
+        let mut frame_buffer = FrameBuffer::new();
+        frame_buffer.test_pattern();
+
+        let before = frame_buffer.frame_hash();
+
+        frame_buffer.flip_pixel(30, 12);
+
+        assert_ne!(frame_buffer.frame_hash(), before);
+
+        frame_buffer.flip_pixel(30, 12);
+
+        assert_eq!(frame_buffer.frame_hash(), before);
+    }
+
+    #[test]
+    fn test_scroll_down() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.flip_pixel(5, 0);
+        frame_buffer.flip_pixel(7, 30);
+        frame_buffer.mark_clean();
+        frame_buffer.scroll_down(3);
+
+        assert!(frame_buffer.get_pixel(5, 3));
+        assert!(!frame_buffer.get_pixel(5, 0));
+        // Scrolled off the bottom, doesn't wrap back in at the top
+        assert!(!frame_buffer.get_pixel(7, 1));
+        assert!(frame_buffer.dirty_rows().iter().all(|dirty| *dirty));
+    }
+
+    #[test]
+    fn test_scroll_up() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.flip_pixel(5, 31);
+        frame_buffer.flip_pixel(7, 1);
+        frame_buffer.scroll_up(2);
+
+        assert!(frame_buffer.get_pixel(5, 29));
+        assert!(!frame_buffer.get_pixel(5, 31));
+        assert!(!frame_buffer.get_pixel(7, 31));
+        assert!(!frame_buffer.get_pixel(7, 1));
+
+        frame_buffer.test_pattern();
+        frame_buffer.scroll_up(32);
+
+        assert_eq!(frame_buffer.frame_hash(), FrameBuffer::new().frame_hash());
+    }
+
+    #[test]
+    fn test_scroll_up_hires() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.set_hires(true);
+        frame_buffer.flip_pixel(100, 63);
+        frame_buffer.scroll_up(15);
+
+        assert!(frame_buffer.get_pixel(100, 48));
+        assert!(!frame_buffer.get_pixel(100, 63));
+    }
+
+    #[test]
+    fn test_scroll_right_and_left() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.flip_pixel(0, 3);
+        frame_buffer.flip_pixel(62, 4);
+        frame_buffer.scroll_right(4);
+
+        assert!(frame_buffer.get_pixel(4, 3));
+        assert!(!frame_buffer.get_pixel(0, 3));
+        // Scrolled off the right edge, doesn't wrap back in on the left
+        assert!(!frame_buffer.get_pixel(2, 4));
+
+        frame_buffer.scroll_left(4);
+
+        assert!(frame_buffer.get_pixel(0, 3));
+        assert!(!frame_buffer.get_pixel(62, 4));
+
+        // Lores rows keep to the low 64 bits
+        frame_buffer.scroll_left(4);
+        frame_buffer.set_hires(false);
+        assert_eq!(frame_buffer.frame_hash(), FrameBuffer::new().frame_hash());
+    }
+
+    #[test]
+    fn test_planes() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        assert_eq!(frame_buffer.selected_planes(), 0b01);
+
+        frame_buffer.flip_plane_pixel(1, 5, 5);
+        frame_buffer.flip_plane_pixel(0, 6, 5);
+        frame_buffer.flip_plane_pixel(1, 6, 5);
+
+        assert_eq!(frame_buffer.pixel_value(5, 5), 2);
+        assert_eq!(frame_buffer.pixel_value(6, 5), 3);
+        assert!(!frame_buffer.get_pixel(5, 5));
+        assert_eq!(&frame_buffer.to_ascii()[5 * 65..5 * 65 + 8], "     ## ");
+
+        // A second plane pixel changes the hash, the first plane alone keeps the old one
+        let mut first_plane_only = FrameBuffer::new();
+        first_plane_only.flip_pixel(6, 5);
+        assert_ne!(frame_buffer.frame_hash(), first_plane_only.frame_hash());
+
+        frame_buffer.select_planes(0b10);
+        frame_buffer.scroll_down(1);
+        assert_eq!(frame_buffer.frame_hash(), {
+            let mut expected = first_plane_only;
+            expected.flip_plane_pixel(1, 5, 6);
+            expected.flip_plane_pixel(1, 6, 6);
+            expected.frame_hash()
+        });
+
+        frame_buffer.select_planes(0xFF);
+        assert_eq!(frame_buffer.selected_planes(), 0b11);
+        assert!(frame_buffer.set_hires(true));
+        assert!((0..128).all(|x| frame_buffer.pixel_value(x, 6) == 0));
+    }
+
+    #[test]
+    fn test_flip_pixel_marks_row_dirty() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.mark_clean();
+        frame_buffer.flip_pixel(10, 5);
+
+        for (row, dirty) in frame_buffer.dirty_rows().iter().enumerate() {
+            assert_eq!(*dirty, row == 5);
+        }
+    }
+
+    #[test]
+    fn test_clear_marks_all_rows_dirty() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.mark_clean();
+        frame_buffer.clear();
+
+        assert!(frame_buffer.dirty_rows().iter().all(|dirty| *dirty));
+    }
+
+    #[test]
+    fn test_to_ascii() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.flip_pixel(0, 0);
+        frame_buffer.flip_pixel(2, 1);
+        frame_buffer.flip_pixel(63, 31);
+
+        let ascii = frame_buffer.to_ascii();
+        let lines: Vec<_> = ascii.lines().collect();
+
+        assert_eq!(lines.len(), 32);
+        assert!(lines.iter().all(|line| line.len() == 64));
+        assert_eq!(lines[0], format!("#{}", " ".repeat(63)));
+        assert_eq!(lines[1], format!("  #{}", " ".repeat(61)));
+        assert_eq!(lines[2], " ".repeat(64));
+        assert_eq!(lines[31], format!("{}#", " ".repeat(63)));
+    }
+
+    #[test]
+    fn test_clear_and_scroll_report_changes() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        assert!(!frame_buffer.clear());
+        assert!(!frame_buffer.scroll_down(4));
+
+        frame_buffer.flip_pixel(10, 5);
+
+        assert!(frame_buffer.scroll_up(2));
+        assert!(frame_buffer.clear());
+        assert!(!frame_buffer.clear());
+
+        assert!(frame_buffer.set_hires(true));
+        assert!(!frame_buffer.set_hires(true));
     }
 }
\ No newline at end of file
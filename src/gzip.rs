@@ -0,0 +1,413 @@
+// Small gzip (RFC 1952) / DEFLATE (RFC 1951) decoder, enough to unpack compressed ROMs
+// without pulling in a compression crate. Only decoding is supported.
+
+pub const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+// Order in which code length code lengths are stored in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+// Decompresses a single member gzip stream, 'limit' caps the output size so a
+// malicious file can't make us allocate without bound
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    if data.len() < 18 || !is_gzip(data) {
+        return Err("Not a gzip stream".to_owned());
+    }
+
+    // Only deflate (8) is defined
+    if data[2] != 8 {
+        return Err(format!("Unsupported gzip compression method {}", data[2]));
+    }
+
+    let flags = data[3];
+    let mut pos = 10;
+
+    if flags & FLAG_EXTRA != 0 {
+        let extra_len = *data.get(pos).ok_or("Truncated gzip header")? as usize
+            | (*data.get(pos + 1).ok_or("Truncated gzip header")? as usize) << 8;
+
+        pos += 2 + extra_len;
+    }
+
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            while *data.get(pos).ok_or("Truncated gzip header")? != 0 {
+                pos += 1;
+            }
+
+            pos += 1;
+        }
+    }
+
+    if flags & FLAG_HCRC != 0 {
+        pos += 2;
+    }
+
+    if pos + 8 > data.len() {
+        return Err("Truncated gzip stream".to_owned());
+    }
+
+    let output = inflate(&data[pos..data.len() - 8], limit)?;
+
+    let trailer = &data[data.len() - 8..];
+    let expected_crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let expected_len = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+
+    if crc32(&output) != expected_crc || output.len() as u32 != expected_len {
+        return Err("Corrupted gzip stream, checksum mismatch".to_owned());
+    }
+
+    Ok(output)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u8,
+}
+
+impl BitReader<'_> {
+    fn read_bit(&mut self) -> Result<u16, String> {
+        let byte = *self.data.get(self.pos).ok_or("Unexpected end of deflate stream")?;
+        let bit = (byte >> self.bit) & 1;
+
+        self.bit += 1;
+
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+
+        Ok(bit as u16)
+    }
+
+    // Least significant bit first, the way DEFLATE packs everything but Huffman codes
+    fn read_bits(&mut self, count: u8) -> Result<u16, String> {
+        let mut value = 0;
+
+        for i in 0..count {
+            value |= self.read_bit()? << i;
+        }
+
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+// Canonical Huffman table stored as symbol counts per code length plus symbols sorted by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+
+        for i in 1..16 {
+            offsets[i] = offsets[i - 1] + counts[i - 1];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for length in 1..16 {
+            code |= reader.read_bit()? as i32;
+
+            let count = self.counts[length] as i32;
+
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+
+        Err("Invalid Huffman code in deflate stream".to_owned())
+    }
+}
+
+fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader { data, pos: 0, bit: 0 };
+    let mut output = vec![];
+
+    loop {
+        let is_final = reader.read_bits(1)? == 1;
+
+        match reader.read_bits(2)? {
+            0 => inflate_stored(&mut reader, &mut output)?,
+
+            1 => {
+                let mut lengths = [0u8; 288];
+
+                lengths[0..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..288].fill(8);
+
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+
+                inflate_block(&mut reader, &mut output, &literals, &distances, limit)?;
+            }
+
+            2 => {
+                let (literals, distances) = read_dynamic_tables(&mut reader)?;
+
+                inflate_block(&mut reader, &mut output, &literals, &distances, limit)?;
+            }
+
+            _ => return Err("Invalid deflate block type".to_owned())
+        }
+
+        if output.len() > limit {
+            return Err(format!("Decompressed data is bigger than {} bytes", limit));
+        }
+
+        if is_final {
+            return Ok(output);
+        }
+    }
+}
+
+fn inflate_stored(reader: &mut BitReader, output: &mut Vec<u8>) -> Result<(), String> {
+    reader.align_to_byte();
+
+    let header = reader.data.get(reader.pos..reader.pos + 4).ok_or("Unexpected end of deflate stream")?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+
+    if len != !nlen {
+        return Err("Corrupted stored deflate block".to_owned());
+    }
+
+    let start = reader.pos + 4;
+    let block = reader.data.get(start..start + len as usize).ok_or("Unexpected end of deflate stream")?;
+
+    output.extend_from_slice(block);
+    reader.pos = start + len as usize;
+
+    Ok(())
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0u8; 19];
+
+    for i in 0..code_length_count {
+        code_length_lengths[CODE_LENGTH_ORDER[i]] = reader.read_bits(3)? as u8;
+    }
+
+    let code_lengths = Huffman::new(&code_length_lengths);
+    let mut lengths = vec![];
+
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_lengths.decode(reader)?;
+
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+
+            16 => {
+                let previous = *lengths.last().ok_or("Repeat with no previous code length")?;
+
+                (previous, 3 + reader.read_bits(2)?)
+            }
+
+            17 => (0, 3 + reader.read_bits(3)?),
+
+            18 => (0, 11 + reader.read_bits(7)?),
+
+            _ => return Err("Invalid code length symbol".to_owned())
+        };
+
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+
+    if lengths.len() != literal_count + distance_count {
+        return Err("Too many code lengths in deflate header".to_owned());
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &Huffman, distances: &Huffman,
+                 limit: usize) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+
+            256 => return Ok(()),
+
+            257..=285 => {
+                let index = symbol - 257;
+                let length = LENGTH_BASE[index] as usize + reader.read_bits(LENGTH_EXTRA_BITS[index])? as usize;
+
+                let distance_symbol = distances.decode(reader)? as usize;
+
+                if distance_symbol >= DISTANCE_BASE.len() {
+                    return Err("Invalid distance symbol in deflate stream".to_owned());
+                }
+
+                let distance = DISTANCE_BASE[distance_symbol] as usize
+                    + reader.read_bits(DISTANCE_EXTRA_BITS[distance_symbol])? as usize;
+
+                if distance > output.len() {
+                    return Err("Deflate distance points before the start of the data".to_owned());
+                }
+
+                // Copies can overlap the bytes they produce, so go one byte at a time
+                for _ in 0..length {
+                    output.push(output[output.len() - distance]);
+                }
+            }
+
+            _ => return Err("Invalid literal/length symbol in deflate stream".to_owned())
+        }
+
+        if output.len() > limit {
+            return Err(format!("Decompressed data is bigger than {} bytes", limit));
+        }
+    }
+}
+
+// The gzip (IEEE) CRC-32, also used to identify ROMs and for PNG chunks in screenshots
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // gzip of 60 05 F0 18 00 E0 12 06, compressed with a fixed Huffman block
+    const FIXED_GZIP: [u8; 28] = [
+        0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4B, 0x60, 0xFD, 0x20, 0xC1, 0xF0,
+        0x40, 0x88, 0x0D, 0x00, 0x69, 0x90, 0x86, 0x9A, 0x08, 0x00, 0x00, 0x00,
+    ];
+
+    // gzip of "CHIP8" in a stored block
+    const STORED_GZIP: [u8; 28] = [
+        0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x05, 0x00, 0xFA, 0xFF, 0x43,
+        0x48, 0x49, 0x50, 0x38, 0xD3, 0xF6, 0x23, 0x6A, 0x05, 0x00, 0x00, 0x00,
+    ];
+
+    // gzip of DYNAMIC_TEXT repeated 4 times, compressed with a dynamic Huffman block
+    const DYNAMIC_TEXT: &[u8] = b"rusty calico c8 emulates the chip8 virtual machine, rusty calico c8 loads gzip roms. ";
+    const DYNAMIC_GZIP: [u8; 93] = [
+        0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xE5, 0x8C, 0xD1, 0x09, 0x80, 0x30,
+        0x0C, 0x05, 0x57, 0x79, 0x03, 0x88, 0xDF, 0x5D, 0x27, 0xC4, 0xA0, 0x81, 0xD4, 0x96, 0x24, 0x15,
+        0x74, 0x7A, 0xFD, 0xD6, 0x11, 0xFC, 0x3C, 0xB8, 0x3B, 0x1F, 0x91, 0x27, 0x98, 0x4C, 0xB9, 0x81,
+        0x0B, 0xA4, 0x0E, 0xA3, 0x94, 0x40, 0x6E, 0x02, 0xDE, 0xB4, 0x17, 0x1C, 0xEA, 0x39, 0xC8, 0x50,
+        0xE9, 0xE1, 0x5D, 0x26, 0xF8, 0xAB, 0xB1, 0x46, 0x4B, 0x60, 0xBD, 0xB4, 0xC3, 0x5B, 0x8D, 0xF9,
+        0x23, 0xFC, 0x6C, 0x7A, 0x03, 0x13, 0x20, 0x79, 0xA3, 0x54, 0x01, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        // The CRC of PNG's IEND chunk type, screenshots use the same CRC
+        assert_eq!(crc32(b"IEND"), 0xAE426082);
+    }
+
+    #[test]
+    fn test_decompress_fixed_block() {
+        assert_eq!(decompress(&FIXED_GZIP, 4096).unwrap(), vec![0x60, 0x05, 0xF0, 0x18, 0x00, 0xE0, 0x12, 0x06]);
+    }
+
+    #[test]
+    fn test_decompress_stored_block() {
+        assert_eq!(decompress(&STORED_GZIP, 4096).unwrap(), b"CHIP8".to_vec());
+    }
+
+    #[test]
+    fn test_decompress_dynamic_block() {
+        assert_eq!(decompress(&DYNAMIC_GZIP, 4096).unwrap(), DYNAMIC_TEXT.repeat(4));
+    }
+
+    #[test]
+    fn test_decompress_errors() {
+        let mut corrupted = FIXED_GZIP;
+        corrupted[20] ^= 0xFF;
+
+        assert!(decompress(&corrupted, 4096).is_err());
+        assert!(decompress(&DYNAMIC_GZIP, 100).is_err());
+        assert!(decompress(&FIXED_GZIP[..15], 4096).is_err());
+        assert!(decompress(b"not gzip at all, not even close", 4096).is_err());
+    }
+}
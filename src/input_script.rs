@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// Input scripts are plain text, one key change per line:
+//   <frame> <hex key> down|up
+// Blank lines and lines starting with '#' are skipped. Unlike replays only the changes are listed, keys
+// keep their state until a later line changes it.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ScriptEvent {
+    pub frame: u64,
+    pub hex_key: u8,
+    pub pressed: bool,
+}
+
+pub struct InputScript {
+    // Sorted by frame, lines for the same frame keep their order
+    events: Vec<ScriptEvent>,
+}
+
+impl InputScript {
+    pub fn new<R: BufRead>(reader: R) -> Result<InputScript, String> {
+        let mut events = vec![];
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let tokens: Vec<_> = line.split_whitespace().collect();
+
+            let event = match tokens[..] {
+                [frame, hex_key, state] => InputScript::parse_event(frame, hex_key, state),
+                _ => None
+            };
+
+            match event {
+                Some(event) => events.push(event),
+                None => return Err(format!("Invalid input script line {}: '{}', expected '<frame> <hex key> down|up'",
+                                           line_number + 1, line))
+            }
+        }
+
+        events.sort_by_key(|event| event.frame);
+
+        Ok(InputScript { events })
+    }
+
+    fn parse_event(frame: &str, hex_key: &str, state: &str) -> Option<ScriptEvent> {
+        let frame = frame.parse().ok()?;
+        let hex_key = u8::from_str_radix(hex_key, 16).ok().filter(|key| *key <= 0xF)?;
+
+        let pressed = match state {
+            "down" => true,
+            "up" => false,
+            _ => return None
+        };
+
+        Some(ScriptEvent { frame, hex_key, pressed })
+    }
+
+    // Key changes to apply right before 'frame' runs
+    pub fn events_at(&self, frame: u64) -> &[ScriptEvent] {
+        let start = self.events.partition_point(|event| event.frame < frame);
+        let end = self.events.partition_point(|event| event.frame <= frame);
+
+        &self.events[start..end]
+    }
+}
+
+// Shared by both frontends
+pub fn open(path: &str) -> Result<InputScript, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Unable to open input script '{}': {}", path, e))?;
+
+    InputScript::new(BufReader::new(file))
+        .map_err(|e| format!("Unable to read input script '{}': {}", path, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_input_script() {
+        let script = InputScript::new(&b"# start the game\n30 5 down\n32 5 up\n\n10 a down\n30 F down\n"[..]).unwrap();
+
+        assert_eq!(script.events_at(10), &[ScriptEvent { frame: 10, hex_key: 0xA, pressed: true }]);
+        assert_eq!(script.events_at(30), &[
+            ScriptEvent { frame: 30, hex_key: 0x5, pressed: true },
+            ScriptEvent { frame: 30, hex_key: 0xF, pressed: true },
+        ]);
+        assert_eq!(script.events_at(32), &[ScriptEvent { frame: 32, hex_key: 0x5, pressed: false }]);
+        assert!(script.events_at(31).is_empty());
+        assert!(script.events_at(100).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_input_script() {
+        assert!(InputScript::new(&b"10 5\n"[..]).is_err());
+        assert!(InputScript::new(&b"10 10 down\n"[..]).is_err());
+        assert!(InputScript::new(&b"10 5 held\n"[..]).is_err());
+        assert!(InputScript::new(&b"-1 5 down\n"[..]).is_err());
+
+        assert_eq!(InputScript::new(&b"10 5 down\nten 5 up\n"[..]).err().unwrap(),
+                   "Invalid input script line 2: 'ten 5 up', expected '<frame> <hex key> down|up'");
+    }
+}
@@ -1,11 +1,51 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
-use std::io::{Error, ErrorKind, Read};
+use std::io::Read;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 use crate::frame_buffer::FrameBuffer;
-use crate::interpreter::InterpreterError::{InvalidOpcode, StackUnderflow};
+use crate::gzip;
+use crate::interpreter::InterpreterError::{InvalidGzip, InvalidLoadAddress, InvalidOpcode, NoRomLoaded, PcOutOfBounds,
+                                           RomOffsetTooLarge, RomTooLarge, RomUnreadable, StackOverflow, StackUnderflow};
+
+// Plain CHIP-8 has 4KB, XO-CHIP uses the whole 16 bit address space
+pub const DEFAULT_MEMORY_SIZE: usize = 4096;
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
+
+// Nested calls allowed before StackOverflow, the COSMAC VIP only had room for 12
+pub const DEFAULT_STACK_LIMIT: usize = 16;
+pub const COSMAC_VIP_STACK_LIMIT: usize = 12;
+
+// Machine cycles the COSMAC VIP had per 60hz frame (1.76MHz, 8 clocks per machine cycle), used by
+// TimingMode::VipCycles
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+// Approximate COSMAC VIP machine cycles per opcode_category(), skips and the time Fx0A spends waiting aren't
+// counted, anything missing costs VIP_DEFAULT_CYCLE_COST
+const VIP_CYCLE_COSTS: [(u16, u32); 34] = [
+    (0x00E0, 24), (0x00EE, 10), (0x1000, 12), (0x2000, 26),
+    (0x3000, 10), (0x4000, 10), (0x5000, 14), (0x6000, 6),
+    (0x7000, 10), (0x8000, 12), (0x8001, 44), (0x8002, 44),
+    (0x8003, 44), (0x8004, 44), (0x8005, 44), (0x8006, 44),
+    (0x8007, 44), (0x800E, 44), (0x9000, 14), (0xA000, 12),
+    (0xB000, 22), (0xC000, 36), (0xD000, 68), (0xE09E, 14),
+    (0xE0A1, 14), (0xF007, 10), (0xF00A, 8), (0xF015, 10),
+    (0xF018, 10), (0xF01E, 16), (0xF029, 16), (0xF033, 84),
+    (0xF055, 64), (0xF065, 64),
+];
+
+const VIP_DEFAULT_CYCLE_COST: u32 = 10;
+
+// Where ROMs are loaded and execution starts, ETI-660 ROMs want 0x600 instead
+pub const DEFAULT_LOAD_ADDRESS: u16 = 0x200;
+
+// Where C8_FONT_SET is loaded, Fx29 has to point into it
+pub const FONT_START_ADDRESS: u16 = 0x050;
+// Fonts and the original interpreter live below this, see Chip8Config::protect_interpreter_mem
+pub const INTERPRETER_MEMORY_END: u16 = 0x200;
 
 pub const C8_FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0,
@@ -26,28 +66,119 @@ pub const C8_FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80,
 ];
 
+// Right after C8_FONT_SET, Fx30 has to point into it
+pub const BIG_FONT_START_ADDRESS: u16 = FONT_START_ADDRESS + C8_FONT_SET.len() as u16;
+
+// SCHIP 8x10 digits, SCHIP itself only had 0-9, A-F follow Octo
+pub const SCHIP_BIG_FONT_SET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C,
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C,
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF,
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C,
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C,
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C,
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C,
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C,
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3,
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC,
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C,
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0,
+];
+
+// SCHIP had 8 RPL user flags, XO-CHIP extends them to 16
+pub const RPL_FLAG_COUNT: usize = 16;
+
+// How many of the most recent (pc, opcode) pairs are kept while tracing
+pub const TRACE_CAPACITY: usize = 64;
+
 #[derive(Debug)]
 pub enum InterpreterError {
     StackUnderflow { pc: u16 },
+    StackOverflow { pc: u16 },
     InvalidOpcode { pc: u16, opcode: u16 },
+    PcOutOfBounds { pc: u16 },
+    // Nothing to run yet, memory past the font is still all zeros
+    NoRomLoaded,
+    // Loading, 'max_size' is the memory left after the load address
+    RomTooLarge { size: usize, max_size: usize },
+    // Odd or outside of memory
+    InvalidLoadAddress { address: u16 },
+    // The skipped header would take up the whole ROM
+    RomOffsetTooLarge { offset: usize, size: usize },
+    RomUnreadable { reason: String },
+    InvalidGzip { reason: String },
 }
 
 impl Display for InterpreterError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match *self {
+        match self {
             StackUnderflow { pc } => {
                 write!(f, "Stack underflow at PC={:#04x}", pc)
             }
 
+            StackOverflow { pc } => {
+                write!(f, "Stack overflow at PC={:#04x}", pc)
+            }
+
             InvalidOpcode { pc, opcode } => {
                 write!(f, "Invalid opcode={:#06x} at PC={:#04x}", opcode, pc)
             }
+
+            PcOutOfBounds { pc } => {
+                write!(f, "PC={:#04x} is outside of memory", pc)
+            }
+
+            NoRomLoaded => {
+                write!(f, "No ROM loaded")
+            }
+
+            RomTooLarge { size, max_size } => {
+                write!(f, "ROM is {} bytes, only {} fit in memory", size, max_size)
+            }
+
+            InvalidLoadAddress { address } => {
+                write!(f, "Load address={:#04x} has to be even, at least 0x200 and inside of memory", address)
+            }
+
+            RomOffsetTooLarge { offset, size } => {
+                write!(f, "ROM offset={} has to be smaller than the ROM size={}", offset, size)
+            }
+
+            RomUnreadable { reason } => {
+                write!(f, "Unable to read ROM: {}", reason)
+            }
+
+            InvalidGzip { reason } => {
+                write!(f, "Invalid gzip ROM: {}", reason)
+            }
         }
     }
 }
 
 impl std::error::Error for InterpreterError {}
 
+// What state_json() writes, memory and frame_buffer are left out unless include_buffers is set
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct InterpreterState<'a> {
+    pc: u16,
+    i: u16,
+    sp: usize,
+    registers: &'a [u8],
+    stack: &'a [u16],
+    delay_timer: u8,
+    sound_timer: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<&'a [u8]>,
+    // One string of '0' and '1' per row, top row first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frame_buffer: Option<Vec<String>>,
+}
+
 // Two below structs used to keep SDL and interpreter module separate
 #[derive(PartialEq)]
 pub enum CalicoEvent {
@@ -56,7 +187,7 @@ pub enum CalicoEvent {
     Other,
 }
 
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum CalicoKey {
     Mk1,
     Mk2,
@@ -77,375 +208,3231 @@ pub enum CalicoKey {
     Other,
 }
 
+// Spelled out instead of relying on declaration order, so reordering the enum can't change the keypad.
+// Keys are laid out like the COSMAC VIP keypad:
+//   1 2 3 4    1 2 3 C
+//   Q W E R    4 5 6 D
+//   A S D F -> 7 8 9 E
+//   Z X C V    A 0 B F
+const CALICO_KEYS_BY_HEX: [CalicoKey; 16] = [
+    CalicoKey::X, CalicoKey::Mk1, CalicoKey::Mk2, CalicoKey::Mk3,
+    CalicoKey::Q, CalicoKey::W, CalicoKey::E, CalicoKey::A,
+    CalicoKey::S, CalicoKey::D, CalicoKey::Z, CalicoKey::C,
+    CalicoKey::Mk4, CalicoKey::R, CalicoKey::F, CalicoKey::V,
+];
+
+impl CalicoKey {
+    pub fn to_hex(&self) -> Option<u8> {
+        CALICO_KEYS_BY_HEX.iter().position(|key| key == self).map(|hex| hex as u8)
+    }
+
+    // Key cap as printed on a keyboard
+    pub fn label(&self) -> &'static str {
+        match self {
+            CalicoKey::Mk1 => "1",
+            CalicoKey::Mk2 => "2",
+            CalicoKey::Mk3 => "3",
+            CalicoKey::Mk4 => "4",
+            CalicoKey::Q => "Q",
+            CalicoKey::W => "W",
+            CalicoKey::E => "E",
+            CalicoKey::R => "R",
+            CalicoKey::A => "A",
+            CalicoKey::S => "S",
+            CalicoKey::D => "D",
+            CalicoKey::F => "F",
+            CalicoKey::Z => "Z",
+            CalicoKey::X => "X",
+            CalicoKey::C => "C",
+            CalicoKey::V => "V",
+            CalicoKey::Other => "?",
+        }
+    }
+
+    // Anything above 0xF is CalicoKey::Other
+    pub fn from_hex(v: u8) -> CalicoKey {
+        CALICO_KEYS_BY_HEX.get(v as usize).copied().unwrap_or(CalicoKey::Other)
+    }
+}
+
+// Result of a single run_frame() call, tells the host what to present
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FrameOutcome {
+    pub redraw: bool,
+    pub play_sound: bool,
+}
+
+pub type FrameResult = Result<FrameOutcome, InterpreterError>;
+
+// Keys held down for a whole frame, what hosts hand to drive_frame() and drive()
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct KeypadState {
+    // Bit N set = hex key N held down, the same as Chip8Interpreter::keypad_state()
+    mask: u16,
+}
+
+impl KeypadState {
+    pub fn from_mask(mask: u16) -> KeypadState {
+        KeypadState { mask }
+    }
+
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    // 'hex_key' is the CHIP8 key 0x0-0xF, anything else is ignored
+    pub fn set(&mut self, hex_key: u8, pressed: bool) {
+        if hex_key > 0xF {
+            return;
+        }
+
+        if pressed {
+            self.mask |= 1 << hex_key;
+        } else {
+            self.mask &= !(1 << hex_key);
+        }
+    }
+
+    pub fn is_pressed(&self, hex_key: u8) -> bool {
+        hex_key <= 0xF && self.mask & (1 << hex_key) != 0
+    }
+}
+
+// How sprite pixels past the right/bottom edge are handled
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ClipQuirk {
+    Wrap,
+    Clip,
+}
+
+// Whether sprites are drawn like the COSMAC VIP did, wrapping past the right edge but clipped at the bottom.
+// Takes priority over ClipQuirk when on
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum VipDrawQuirk {
+    Off,
+    WrapRightClipBottom,
+}
+
+// Whether 8xy6/8xyE shift Vy into Vx (COSMAC VIP) or shift Vx in place (SCHIP)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ShiftQuirk {
+    ShiftVy,
+    ShiftVx,
+}
+
+// Whether Fx55/Fx65 leave I pointing past the last register (COSMAC VIP) or untouched (SCHIP)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MemoryQuirk {
+    IncrementI,
+    LeaveI,
+}
+
+// Whether Fx0A stores the key as soon as it's pressed (SCHIP) or once it's released again (COSMAC VIP)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WaitKeyQuirk {
+    OnPress,
+    OnRelease,
+}
+
+// Whether Ex9E/ExA1 see a key as pressed for as long as it's held (level) or only during the frame it went down
+// (edge), the latter keeps OS key repeat and long presses from triggering an action every frame
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum KeyTriggerQuirk {
+    Level,
+    Edge,
+}
+
+// How run_frame() decides when a frame is over, a flat number of instructions or the machine cycles the
+// COSMAC VIP would have spent on them (Dxyn also waits for the display like it did there)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TimingMode {
+    Instructions,
+    VipCycles,
+}
+
+// Everything the interpreter needs to know up front, keeps new() from growing an argument per option
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Chip8Config {
+    pub sound_enabled: bool,
+    pub shift_quirk: ShiftQuirk,
+    pub memory_quirk: MemoryQuirk,
+    pub clip_quirk: ClipQuirk,
+    pub vip_draw_quirk: VipDrawQuirk,
+    pub wait_key_quirk: WaitKeyQuirk,
+    pub key_trigger_quirk: KeyTriggerQuirk,
+    pub display_wait: bool,
+    pub timing_mode: TimingMode,
+    // Log invalid opcodes and skip them instead of stopping
+    pub lenient: bool,
+    // Warn on stderr when an 8xyN op takes VF as X or Y, those often behave differently between platforms
+    pub warn_vf: bool,
+    // In bytes, clamped between DEFAULT_MEMORY_SIZE and XO_CHIP_MEMORY_SIZE since addresses are 16 bit. Every
+    // access relative to I (Fx33, Fx55, Fx65 and Dxyn) wraps around to 0x000 past the end of memory, the PC
+    // doesn't and stops with PcOutOfBounds instead
+    pub memory_size: usize,
+    // Deepest the call stack may get, see DEFAULT_STACK_LIMIT
+    pub stack_limit: usize,
+    // Count executed instructions per opcode_category(), see opcode_counts()
+    pub profile: bool,
+    // Run 0nnn (SYS) as a call to nnn instead of skipping it, only a few very old ROMs want this
+    pub legacy_sys: bool,
+    // Ignore Fx33/Fx55 writes below INTERPRETER_MEMORY_END so a buggy ROM can't overwrite the font
+    pub protect_interpreter_mem: bool,
+    // Keep every executed (pc, opcode) until drain_execution_log(), unbounded unlike the recent trace
+    pub log_executions: bool,
+    // Where the ROM goes and the PC starts, see DEFAULT_LOAD_ADDRESS
+    pub load_address: u16,
+    // Bytes of header skipped at the start of the (unpacked) ROM before loading it
+    pub rom_offset: usize,
+    // Makes Cxnn repeat the same numbers every run, None = seeded from the OS
+    pub rng_seed: Option<u64>,
+}
+
+impl Default for Chip8Config {
+    fn default() -> Self {
+        Chip8Config {
+            sound_enabled: true,
+            shift_quirk: ShiftQuirk::ShiftVx,
+            memory_quirk: MemoryQuirk::LeaveI,
+            clip_quirk: ClipQuirk::Wrap,
+            vip_draw_quirk: VipDrawQuirk::Off,
+            wait_key_quirk: WaitKeyQuirk::OnPress,
+            key_trigger_quirk: KeyTriggerQuirk::Level,
+            display_wait: false,
+            timing_mode: TimingMode::Instructions,
+            lenient: false,
+            warn_vf: false,
+            memory_size: DEFAULT_MEMORY_SIZE,
+            stack_limit: DEFAULT_STACK_LIMIT,
+            profile: false,
+            legacy_sys: false,
+            protect_interpreter_mem: false,
+            log_executions: false,
+            load_address: DEFAULT_LOAD_ADDRESS,
+            rom_offset: 0,
+            rng_seed: None,
+        }
+    }
+}
+
 pub struct Chip8Interpreter {
     pub frame_buffer: FrameBuffer,
-    pub draw_flag: bool,
-    memory: [u8; 4096],
+    draw_flag: bool,
+    // Set by 00FE/00FF, frontends sized for the old resolution have to start over
+    resolution_changed: bool,
+    memory: Vec<u8>,
+    load_address: u16,
+    rom_offset: usize,
+    rom_size: usize,
+    // Set by load_rom(), load_rom_bytes() and load_test_pattern(), an empty ROM still counts
+    rom_loaded: bool,
     stack: Vec<u16>,
+    stack_limit: usize,
     keypad_status: [bool; 16],
+    // Keys pressed since the end of the last frame, so a press and release between two frames isn't lost to Fx0A
+    key_press_latch: u16,
+    // Keys that went down since the end of the last frame, what Ex9E/ExA1 read with KeyTriggerQuirk::Edge
+    new_key_presses: [bool; 16],
+    rng: StdRng,
     general_registers: [u8; 16],
+    // Fx75/Fx85, the HP48 kept these across resets so they aren't cleared with the rest
+    rpl_flags: [u8; RPL_FLAG_COUNT],
     register_pc: u16,
     register_i: u16,
     delay_timer: u8,
     sound_timer: u8,
     sound_enabled: bool,
+    clip_quirk: ClipQuirk,
+    vip_draw_quirk: VipDrawQuirk,
+    shift_quirk: ShiftQuirk,
+    memory_quirk: MemoryQuirk,
+    wait_key_quirk: WaitKeyQuirk,
+    // Key Fx0A saw go down and is waiting to see released, only used with WaitKeyQuirk::OnRelease
+    wait_key_pressed: Option<u8>,
+    key_trigger_quirk: KeyTriggerQuirk,
+    display_wait: bool,
+    // Set by a draw when display_wait is on, ends the current frame
+    waiting_for_vblank: bool,
+    timing_mode: TimingMode,
+    // VipCycles only, machine cycles left in the current frame, negative when the last instruction ran over
+    cycle_budget: i64,
+    lenient: bool,
+    warn_vf: bool,
+    // Addresses already warned about, so a loop doesn't repeat the warning every frame
+    vf_warning_pcs: HashSet<u16>,
+    trace_enabled: bool,
+    trace: VecDeque<(u16, u16)>,
+    profile: bool,
+    opcode_counts: HashMap<u16, u64>,
+    legacy_sys: bool,
+    protect_interpreter_mem: bool,
+    log_executions: bool,
+    execution_log: Vec<(u16, u16)>,
+    breakpoints: HashSet<u16>,
+    breakpoint_hit: bool,
+    write_watches: Vec<(u16, u16)>,
+    write_watch_hit: Option<u16>,
     current_opcode: u16,
 }
 
 impl Chip8Interpreter {
-    pub fn new(sound_enabled: bool) -> Chip8Interpreter {
+    pub fn new(config: Chip8Config) -> Chip8Interpreter {
         let mut interpreter = Chip8Interpreter {
             frame_buffer: FrameBuffer::new(),
             draw_flag: false,
-            memory: [0; 4096],
+            resolution_changed: false,
+            memory: vec![0; config.memory_size.clamp(DEFAULT_MEMORY_SIZE, XO_CHIP_MEMORY_SIZE)],
+            load_address: config.load_address,
+            rom_offset: config.rom_offset,
+            rom_size: 0,
+            rom_loaded: false,
             stack: vec![],
+            stack_limit: config.stack_limit,
             keypad_status: [false; 16],
+            key_press_latch: 0,
+            new_key_presses: [false; 16],
+            rng: match config.rng_seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy()
+            },
             general_registers: [0x00; 16],
-            register_pc: 0x200,
+            rpl_flags: [0x00; RPL_FLAG_COUNT],
+            register_pc: config.load_address,
             register_i: 0x00,
             delay_timer: 0x00,
             sound_timer: 0x00,
-            sound_enabled,
+            sound_enabled: config.sound_enabled,
+            clip_quirk: config.clip_quirk,
+            vip_draw_quirk: config.vip_draw_quirk,
+            shift_quirk: config.shift_quirk,
+            memory_quirk: config.memory_quirk,
+            wait_key_quirk: config.wait_key_quirk,
+            wait_key_pressed: None,
+            key_trigger_quirk: config.key_trigger_quirk,
+            display_wait: config.display_wait,
+            waiting_for_vblank: false,
+            timing_mode: config.timing_mode,
+            cycle_budget: 0,
+            lenient: config.lenient,
+            warn_vf: config.warn_vf,
+            vf_warning_pcs: HashSet::new(),
+            trace_enabled: false,
+            trace: VecDeque::with_capacity(TRACE_CAPACITY),
+            profile: config.profile,
+            opcode_counts: HashMap::new(),
+            legacy_sys: config.legacy_sys,
+            protect_interpreter_mem: config.protect_interpreter_mem,
+            log_executions: config.log_executions,
+            execution_log: vec![],
+            breakpoints: HashSet::new(),
+            breakpoint_hit: false,
+            write_watches: vec![],
+            write_watch_hit: None,
             current_opcode: 0x0000,
         };
 
-        for i in 0..C8_FONT_SET.len() {
-            interpreter.memory[i + 0x050] = C8_FONT_SET[i];
-        }
+        let font_start = FONT_START_ADDRESS as usize;
+        interpreter.memory[font_start..font_start + C8_FONT_SET.len()].copy_from_slice(&C8_FONT_SET);
+
+        let big_font_start = BIG_FONT_START_ADDRESS as usize;
+        interpreter.memory[big_font_start..big_font_start + SCHIP_BIG_FONT_SET.len()].copy_from_slice(&SCHIP_BIG_FONT_SET);
 
         interpreter
     }
 
-    pub fn load_rom(&mut self, path: &str) -> Result<(), std::io::Error> {
-        let mut binary_file = File::open(path)?;
+    pub fn load_rom(&mut self, path: &str) -> Result<(), InterpreterError> {
         let mut binary_data = Vec::new();
 
-        binary_file.read_to_end(&mut binary_data)?;
+        File::open(path)
+            .and_then(|mut binary_file| binary_file.read_to_end(&mut binary_data))
+            .map_err(|e| RomUnreadable { reason: e.to_string() })?;
 
-        if binary_data.len() > 4096 - 0x200 {
-            // ErrorKind::FileToLarge unstable for now..
-            return Err(Error::new(ErrorKind::Other, "Binary too big for CHIP8"));
-        }
-
-        for i in 0..binary_data.len() {
-            self.memory[i + 0x200] = binary_data[i];
-        }
-
-        Ok(())
+        self.load_rom_bytes(&binary_data)
     }
 
-    pub fn handle_event(&mut self, event: CalicoEvent, key: CalicoKey) {
-        if key == CalicoKey::Other || event == CalicoEvent::Other {
-            return;
+    // Gzip compressed ROMs are detected by their magic bytes and unpacked first, the header (rom_offset) is
+    // skipped after that
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<(), InterpreterError> {
+        let load_address = self.load_address as usize;
+
+        // Below INTERPRETER_MEMORY_END the ROM would overwrite the font
+        if !load_address.is_multiple_of(2) || load_address < INTERPRETER_MEMORY_END as usize ||
+            load_address >= self.memory.len() {
+            return Err(InvalidLoadAddress { address: self.load_address });
         }
 
-        let key_index = key as usize;
+        let max_size = self.memory.len() - load_address;
+        let decompressed;
 
-        self.keypad_status[key_index] = if event == CalicoEvent::KeyDown {
-            true
+        let binary_data = if gzip::is_gzip(data) {
+            decompressed = gzip::decompress(data, max_size + self.rom_offset)
+                .map_err(|reason| InvalidGzip { reason })?;
+
+            &decompressed[..]
         } else {
-            false
+            data
         };
-    }
 
-    pub fn tick_timers(&mut self) {
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+        if self.rom_offset > 0 && self.rom_offset >= binary_data.len() {
+            return Err(RomOffsetTooLarge { offset: self.rom_offset, size: binary_data.len() });
         }
 
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+        let binary_data = &binary_data[self.rom_offset..];
+
+        if binary_data.len() > max_size {
+            return Err(RomTooLarge { size: binary_data.len(), max_size });
         }
-    }
 
-    pub fn should_play_sound(&self) -> bool {
-        self.sound_timer != 0 && self.sound_enabled
-    }
+        self.memory[load_address..load_address + binary_data.len()].copy_from_slice(binary_data);
 
-    fn get_x_from_opcode(&self) -> usize {
-        ((self.current_opcode & 0x0F00) >> 8) as usize
-    }
+        self.rom_size = binary_data.len();
+        self.rom_loaded = true;
 
-    fn get_y_from_opcode(&self) -> usize {
-        ((self.current_opcode & 0x00F0) >> 4) as usize
+        Ok(())
     }
 
-    fn get_nnn_from_opcode(&self) -> u16 {
-        self.current_opcode & 0x0FFF
+    // Shows FrameBuffer::test_pattern() instead of a ROM, the program is a single jump to itself
+    // so the frontend keeps rendering without anything changing the screen
+    pub fn load_test_pattern(&mut self) {
+        // The configured load address doesn't matter here
+        self.load_address = DEFAULT_LOAD_ADDRESS;
+        self.register_pc = DEFAULT_LOAD_ADDRESS;
+
+        // 1200 = JP 0x200
+        self.memory[0x200] = 0x12;
+        self.memory[0x201] = 0x00;
+        self.rom_size = 2;
+        self.rom_loaded = true;
+
+        self.frame_buffer.test_pattern();
+        self.draw_flag = true;
     }
 
-    fn get_nn_from_opcode(&self) -> u8 {
-        (self.current_opcode & 0x00FF) as u8
+    pub fn is_rom_loaded(&self) -> bool {
+        self.rom_loaded
     }
 
-    fn get_n_from_opcode(&self) -> u8 {
-        (&self.current_opcode & 0x000F) as u8
+    // The ROM as loaded at the load address, empty until load_rom() succeeds
+    pub fn rom(&self) -> &[u8] {
+        let load_address = self.load_address as usize;
+
+        &self.memory[load_address..load_address + self.rom_size]
     }
 
-    fn draw(&mut self, x: usize, y: usize, height: u8) {
-        let x_cord = self.general_registers[x];
-        let y_cord = self.general_registers[y];
+    // Best effort scan of the loaded ROM for words this interpreter can't execute, data embedded
+    // in the ROM can't be told apart from code so some hits may never actually run
+    pub fn validate_rom(&self) -> Vec<(u16, u16)> {
+        let mut invalid_opcodes = vec![];
 
-        let mut pixel_flipped = false;
+        let load_address = self.load_address as usize;
 
-        for diff_y in 0..height {
-            let r = self.memory[(self.register_i + diff_y as u16) as usize];
+        for address in (load_address..load_address + self.rom_size).step_by(2) {
+            let opcode = (self.memory[address] as u16) << 8 | self.memory[address + 1] as u16;
 
-            for diff_x in 0..8 {
-                if r & (1 << (7 - diff_x)) != 0 {
-                    self.frame_buffer.flip_pixel(x_cord + diff_x, y_cord + diff_y);
-                    if !self.frame_buffer.get_pixel(x_cord + diff_x, y_cord + diff_y) {
-                        pixel_flipped = true;
-                    }
-                }
+            if !Chip8Interpreter::is_valid_opcode(opcode) {
+                invalid_opcodes.push((address as u16, opcode));
             }
         }
 
-        self.general_registers[0xF] = pixel_flipped as u8;
-        self.draw_flag = true;
+        invalid_opcodes
     }
 
-    fn fn_call(&mut self, address: u16) {
-        self.stack.push(self.register_pc);
-        self.register_pc = address;
+    // Has to stay in sync with the arms of execute_next_instruction()
+    fn is_valid_opcode(opcode: u16) -> bool {
+        match opcode & 0xF000 {
+            0x8000 => matches!(opcode & 0x000F, 0x0..=0x7 | 0xE),
+            0xE000 => matches!(opcode & 0x00FF, 0x9E | 0xA1),
+            0xF000 => opcode == 0xF000 || matches!(opcode & 0x00FF, 0x01 | 0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x30 | 0x33 | 0x55 | 0x65 | 0x75 | 0x85),
+            _ => true
+        }
     }
 
-    fn fn_return(&mut self) -> Result<(), InterpreterError> {
-        match self.stack.pop() {
-            Some(val) => self.register_pc = val,
-            None => return Err(StackUnderflow { pc: self.register_pc })
-        }
+    pub fn set_clip_quirk(&mut self, clip_quirk: ClipQuirk) {
+        self.clip_quirk = clip_quirk;
+    }
 
-        Ok(())
+    pub fn set_vip_draw_quirk(&mut self, vip_draw_quirk: VipDrawQuirk) {
+        self.vip_draw_quirk = vip_draw_quirk;
     }
 
-    pub fn execute_next_instruction(&mut self) -> Result<(), InterpreterError> {
-        let hi_byte = self.memory[self.register_pc as usize];
-        let lo_byte = self.memory[(self.register_pc + 1) as usize];
+    pub fn set_shift_quirk(&mut self, shift_quirk: ShiftQuirk) {
+        self.shift_quirk = shift_quirk;
+    }
 
-        self.current_opcode = (hi_byte as u16) << 8 | lo_byte as u16;
-        self.register_pc += 2;
+    pub fn set_memory_quirk(&mut self, memory_quirk: MemoryQuirk) {
+        self.memory_quirk = memory_quirk;
+    }
 
-        match self.current_opcode & 0xF000 {
-            0x0000 => {
-                match self.current_opcode {
-                    0x00ee => self.fn_return()?,
+    pub fn set_wait_key_quirk(&mut self, wait_key_quirk: WaitKeyQuirk) {
+        self.wait_key_quirk = wait_key_quirk;
+        self.wait_key_pressed = None;
+    }
 
-                    0x00e0 => {
-                        self.frame_buffer.clear();
-                        self.draw_flag = true;
-                    }
+    pub fn set_key_trigger_quirk(&mut self, key_trigger_quirk: KeyTriggerQuirk) {
+        self.key_trigger_quirk = key_trigger_quirk;
+    }
 
-                    _ => self.fn_call(self.get_nnn_from_opcode())
-                }
-            }
+    // When on, at most one sprite is drawn per frame like on the original hardware
+    pub fn set_display_wait(&mut self, display_wait: bool) {
+        self.display_wait = display_wait;
+    }
 
-            0x1000 => self.register_pc = self.get_nnn_from_opcode(),
+    pub fn set_trace_enabled(&mut self, enabled: bool) {
+        self.trace_enabled = enabled;
 
-            0x2000 => self.fn_call(self.get_nnn_from_opcode()),
+        if !enabled {
+            self.trace.clear();
+        }
+    }
 
-            0x3000 => {
-                if self.general_registers[self.get_x_from_opcode()] == self.get_nn_from_opcode() {
-                    self.register_pc += 2;
-                }
-            }
+    // Oldest first, at most TRACE_CAPACITY entries
+    pub fn recent_trace(&self) -> &VecDeque<(u16, u16)> {
+        &self.trace
+    }
 
-            0x4000 => {
-                if self.general_registers[self.get_x_from_opcode()] != self.get_nn_from_opcode() {
-                    self.register_pc += 2;
-                }
-            }
+    // Oldest first, everything executed since the last drain, stays empty unless Chip8Config::log_executions was set
+    pub fn drain_execution_log(&mut self) -> impl Iterator<Item=(u16, u16)> + '_ {
+        self.execution_log.drain(..)
+    }
 
-            0x5000 => {
-                if self.general_registers[self.get_x_from_opcode()] == self.general_registers[self.get_y_from_opcode()] {
-                    self.register_pc += 2;
-                }
-            }
+    // Executions per opcode_category(), stays empty unless Chip8Config::profile was set
+    pub fn opcode_counts(&self) -> &HashMap<u16, u64> {
+        &self.opcode_counts
+    }
 
-            0x6000 => self.general_registers[self.get_x_from_opcode()] = self.get_nn_from_opcode(),
+    // The opcode with its operands zeroed, e.g. 8xy4 => 0x8004 and Fx65 => 0xF065
+    pub fn opcode_category(opcode: u16) -> u16 {
+        match opcode & 0xF000 {
+            0x0000 if matches!(opcode, 0x00E0 | 0x00EE | 0x00FB | 0x00FC | 0x00FE | 0x00FF) => opcode,
+            0x0000 if matches!(opcode & 0xFFF0, 0x00C0 | 0x00D0) => opcode & 0xFFF0,
+            0x8000 => opcode & 0xF00F,
+            0xE000 | 0xF000 => opcode & 0xF0FF,
+            _ => opcode & 0xF000
+        }
+    }
 
-            0x7000 => {
-                let res = self.general_registers[self.get_x_from_opcode()].wrapping_add(self.get_nn_from_opcode());
+    pub fn vip_cycle_cost(opcode: u16) -> u32 {
+        let category = Chip8Interpreter::opcode_category(opcode);
 
-                self.general_registers[self.get_x_from_opcode()] = res;
-            }
+        VIP_CYCLE_COSTS.iter()
+            .find(|(opcode, _)| *opcode == category)
+            .map_or(VIP_DEFAULT_CYCLE_COST, |(_, cost)| *cost)
+    }
 
-            0x8000 => {
-                match self.current_opcode & 0x000F {
-                    0x0 => self.general_registers[self.get_x_from_opcode()] = self.general_registers[self.get_y_from_opcode()],
+    fn record_trace(&mut self, pc: u16, opcode: u16) {
+        if self.trace.len() == TRACE_CAPACITY {
+            self.trace.pop_front();
+        }
 
-                    0x1 => self.general_registers[self.get_x_from_opcode()] |= self.general_registers[self.get_y_from_opcode()],
+        self.trace.push_back((pc, opcode));
+    }
 
-                    0x2 => self.general_registers[self.get_x_from_opcode()] &= self.general_registers[self.get_y_from_opcode()],
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
 
-                    0x3 => self.general_registers[self.get_x_from_opcode()] ^= self.general_registers[self.get_y_from_opcode()],
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
 
-                    0x4 => {
-                        let reg_x = self.general_registers[self.get_x_from_opcode()];
-                        let reg_y = self.general_registers[self.get_y_from_opcode()];
+    // True when the last step stopped at a breakpoint instead of executing,
+    // the next step resumes by executing the instruction at that PC
+    pub fn breakpoint_hit(&self) -> bool {
+        self.breakpoint_hit
+    }
 
-                        let result = reg_x as u16 + reg_y as u16;
+    // Both ends inclusive
+    pub fn add_write_watch(&mut self, start: u16, end: u16) {
+        self.write_watches.push((start, end));
+    }
 
-                        self.general_registers[self.get_x_from_opcode()] = result as u8;
-                        self.general_registers[0xF] = (result > 0xFF) as u8;
-                    }
+    pub fn remove_write_watch(&mut self, start: u16, end: u16) {
+        self.write_watches.retain(|watch| *watch != (start, end));
+    }
 
-                    0x5 => {
-                        let reg_x = self.general_registers[self.get_x_from_opcode()];
-                        let reg_y = self.general_registers[self.get_y_from_opcode()];
+    // Address of the first watched write made by the last executed instruction
+    pub fn write_watch_hit(&self) -> Option<u16> {
+        self.write_watch_hit
+    }
 
-                        let result = (reg_x as i16) - (reg_y as i16);
+    // Addresses computed from I wrap around the end of memory instead of indexing past it
+    fn wrap_address(&self, address: usize) -> usize {
+        address % self.memory.len()
+    }
 
-                        self.general_registers[self.get_x_from_opcode()] = (result % (0x100 as i16)) as u8;
-                        self.general_registers[0xF] = (result >= 0) as u8;
-                    }
+    // Every instruction that stores into memory has to go through here so watches see it
+    fn write_memory(&mut self, address: usize, value: u8) {
+        if self.protect_interpreter_mem && address < INTERPRETER_MEMORY_END as usize {
+            return;
+        }
 
-                    0x6 => {
-                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+        self.memory[address] = value;
 
-                        self.general_registers[0xF] = ((reg_x & 1) == 1) as u8;
-                        self.general_registers[self.get_x_from_opcode()] >>= 1;
-                    }
+        if self.write_watch_hit.is_none()
+            && self.write_watches.iter().any(|&(start, end)| (start as usize..=end as usize).contains(&address)) {
+            self.write_watch_hit = Some(address as u16);
+        }
+    }
 
-                    0x7 => {
-                        let reg_x = self.general_registers[self.get_x_from_opcode()];
-                        let reg_y = self.general_registers[self.get_y_from_opcode()];
+    // Read-only view of memory for debuggers, the range is clamped to the end of memory
+    pub fn memory_slice(&self, start: u16, len: u16) -> &[u8] {
+        let start = (start as usize).min(self.memory.len());
+        let end = (start + len as usize).min(self.memory.len());
 
-                        let result = reg_y as i16 - reg_x as i16;
+        &self.memory[start..end]
+    }
 
-                        self.general_registers[self.get_x_from_opcode()] = (result % (0x100 as i16)) as u8;
-                        self.general_registers[0xF] = (result >= 0) as u8;
-                    }
+    // The whole memory image including the font, handy for studying self-modifying ROMs
+    pub fn export_memory(&self) -> &[u8] {
+        &self.memory
+    }
 
-                    0xE => {
-                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+    pub fn registers(&self) -> &[u8] {
+        &self.general_registers
+    }
 
-                        self.general_registers[0xF] = (reg_x & 0b10000000 == 0b10000000) as u8;
-                        self.general_registers[self.get_x_from_opcode()] <<= 1;
-                    }
+    pub fn rpl_flags(&self) -> &[u8] {
+        &self.rpl_flags
+    }
 
-                    _ => return Err(InvalidOpcode { pc: self.register_pc - 2, opcode: self.current_opcode })
-                }
-            }
+    // Flags past RPL_FLAG_COUNT are ignored, missing ones stay as they were
+    pub fn set_rpl_flags(&mut self, flags: &[u8]) {
+        let count = flags.len().min(RPL_FLAG_COUNT);
 
-            0x9000 => {
-                if self.general_registers[self.get_x_from_opcode()] != self.general_registers[self.get_y_from_opcode()] {
-                    self.register_pc += 2;
-                }
-            }
+        self.rpl_flags[..count].copy_from_slice(&flags[..count]);
+    }
 
-            0xA000 => self.register_i = self.get_nnn_from_opcode(),
+    pub fn pc(&self) -> u16 {
+        self.register_pc
+    }
 
-            0xB000 => self.register_pc = self.get_nnn_from_opcode().wrapping_add(self.general_registers[0] as u16),
+    pub fn index_register(&self) -> u16 {
+        self.register_i
+    }
 
-            0xC000 => {
-                let random_byte = rand::thread_rng().gen::<u8>() & self.get_nn_from_opcode();
+    // The last executed opcode and its decoded fields, e.g. 8344 is x = 3, y = 4, n = 4, for debugger views
+    pub fn current_opcode(&self) -> u16 {
+        self.current_opcode
+    }
 
-                self.general_registers[self.get_x_from_opcode()] = random_byte;
-            }
+    pub fn opcode_x(&self) -> u8 {
+        self.get_x_from_opcode() as u8
+    }
 
-            0xD000 => self.draw(self.get_x_from_opcode(), self.get_y_from_opcode(), self.get_n_from_opcode()),
+    pub fn opcode_y(&self) -> u8 {
+        self.get_y_from_opcode() as u8
+    }
 
-            0xE000 => {
-                match self.current_opcode & 0x00FF {
-                    0x9E => {
-                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+    pub fn opcode_n(&self) -> u8 {
+        self.get_n_from_opcode()
+    }
 
-                        if self.keypad_status[reg_x as usize] {
-                            self.register_pc += 2;
-                        }
-                    }
+    pub fn opcode_nn(&self) -> u8 {
+        self.get_nn_from_opcode()
+    }
 
-                    0xA1 => {
-                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+    pub fn opcode_nnn(&self) -> u16 {
+        self.get_nnn_from_opcode()
+    }
 
-                        if !self.keypad_status[reg_x as usize] {
-                            self.register_pc += 2;
-                        }
-                    }
+    // Return addresses, oldest call first
+    pub fn stack_slice(&self) -> &[u16] {
+        &self.stack
+    }
 
-                    _ => return Err(InvalidOpcode { pc: self.register_pc - 2, opcode: self.current_opcode })
-                }
-            }
+    // Snapshot for external debug tools, only built on request so it stays off the hot path,
+    // 'include_buffers' adds both the whole memory and the frame buffer
+    #[cfg(feature = "serde")]
+    pub fn state_json(&self, include_buffers: bool) -> String {
+        let frame_buffer = include_buffers.then(|| {
+            let (width, height) = self.frame_buffer.resolution();
+
+            (0..height)
+                .map(|y| (0..width).map(|x| if self.frame_buffer.get_pixel(x, y) { '1' } else { '0' }).collect())
+                .collect()
+        });
+
+        let state = InterpreterState {
+            pc: self.register_pc,
+            i: self.register_i,
+            sp: self.stack.len(),
+            registers: &self.general_registers,
+            stack: &self.stack,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            memory: include_buffers.then_some(&self.memory[..]),
+            frame_buffer,
+        };
 
-            0xF000 => {
-                match self.current_opcode & 0x00FF {
-                    0x07 => self.general_registers[self.get_x_from_opcode()] = self.delay_timer,
+        serde_json::to_string(&state).expect("Only numbers and strings, serializing can't fail")
+    }
 
-                    0x0A => {
-                        let mut key_pressed = false;
+    // Makes Cxnn deterministic, needed to replay recorded input
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
 
-                        for i in 1..16 {
-                            if self.keypad_status[i] {
-                                self.general_registers[self.get_x_from_opcode()] = i as u8;
-                                key_pressed = true;
-                            }
-                        }
+    // Bit N set = hex key N held down
+    pub fn keypad_state(&self) -> u16 {
+        self.keypad_status.iter()
+            .enumerate()
+            .fold(0, |mask, (key, pressed)| mask | ((*pressed as u16) << key))
+    }
 
-                        // If not pressed, stay on this instruction until pressed
-                        if !key_pressed
-                        {
-                            self.register_pc -= 2;
-                        }
-                    }
+    pub fn set_keypad_state(&mut self, mask: u16) {
+        for key in 0..16 {
+            self.set_key(key, mask & (1 << key) != 0);
+        }
+    }
 
-                    0x15 => self.delay_timer = self.general_registers[self.get_x_from_opcode()],
+    // Releases every key, for when the frontend stops getting key up events (e.g. the window lost focus)
+    pub fn clear_keys(&mut self) {
+        self.set_keypad_state(0);
+    }
 
-                    0x18 => self.sound_timer = self.general_registers[self.get_x_from_opcode()],
+    pub fn handle_event(&mut self, event: CalicoEvent, key: CalicoKey) {
+        if event == CalicoEvent::Other {
+            return;
+        }
 
-                    0x1E => {
-                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+        if let Some(hex_key) = key.to_hex() {
+            self.set_key(hex_key, event == CalicoEvent::KeyDown);
+        }
+    }
 
-                        self.register_i = self.register_i.wrapping_add(reg_x as u16);
+    // 'hex_key' is the CHIP8 key 0x0-0xF, anything else is ignored
+    pub fn set_key(&mut self, hex_key: u8, pressed: bool) {
+        if let Some(status) = self.keypad_status.get_mut(hex_key as usize) {
+            if pressed && !*status {
+                self.key_press_latch |= 1 << hex_key;
+                self.new_key_presses[hex_key as usize] = true;
+            }
+
+            *status = pressed;
+        }
+    }
+
+    pub fn key_state(&self, hex_key: u8) -> bool {
+        self.keypad_status.get(hex_key as usize).copied().unwrap_or(false)
+    }
+
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    pub fn set_sound_enabled(&mut self, on: bool) {
+        self.sound_enabled = on;
+    }
+
+    pub fn toggle_sound(&mut self) {
+        self.sound_enabled = !self.sound_enabled;
+    }
+
+    pub fn should_play_sound(&self) -> bool {
+        self.sound_timer != 0 && self.sound_enabled
+    }
+
+    // Returns whether the screen changed since the last call and clears the flag
+    pub fn take_draw_flag(&mut self) -> bool {
+        std::mem::replace(&mut self.draw_flag, false)
+    }
+
+    // True once after the frame buffer switched between 64 x 32 and 128 x 64, see FrameBuffer::resolution()
+    pub fn take_resolution_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.resolution_changed, false)
+    }
+
+    // Runs one 60hz frame worth of instructions, doesn't touch threads or SDL so any host can drive it.
+    // 'cycles_per_frame' is ignored with TimingMode::VipCycles, VIP_CYCLES_PER_FRAME is used instead
+    pub fn run_frame(&mut self, cycles_per_frame: u32) -> Result<FrameOutcome, InterpreterError> {
+        self.waiting_for_vblank = false;
+
+        match self.timing_mode {
+            TimingMode::Instructions => {
+                for _ in 0..cycles_per_frame {
+                    self.execute_next_instruction()?;
+
+                    if self.frame_interrupted() {
+                        break;
                     }
+                }
+            }
 
-                    0x29 => self.register_i = (self.general_registers[self.get_x_from_opcode()] as u16) * 5,
+            TimingMode::VipCycles => {
+                // An instruction that ran over the budget is paid for by the next frame
+                self.cycle_budget += VIP_CYCLES_PER_FRAME as i64;
 
-                    0x33 => {
-                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+                while self.cycle_budget > 0 {
+                    let opcode = self.execute_next_instruction()?;
 
-                        self.memory[self.register_i as usize] = reg_x / 100;
-                        self.memory[self.register_i as usize + 1] = (reg_x / 10) % 10;
-                        self.memory[self.register_i as usize + 2] = reg_x % 10;
+                    if self.breakpoint_hit {
+                        break;
                     }
 
-                    0x55 => {
-                        let end_index = self.get_x_from_opcode();
+                    self.cycle_budget -= Chip8Interpreter::vip_cycle_cost(opcode) as i64;
 
-                        for i in 0..end_index + 1 {
-                            self.memory[self.register_i as usize + i] = self.general_registers[i];
-                        }
+                    // Time left after waiting for the display is spent idling
+                    if self.frame_interrupted() {
+                        self.cycle_budget = self.cycle_budget.min(0);
+                        break;
                     }
+                }
+            }
+        }
 
-                    0x65 => {
-                        for i in 0..=self.get_x_from_opcode() {
-                            self.general_registers[i] = self.memory[self.register_i as usize + i];
-                        }
+        // Presses only have to survive until the frame after them
+        self.key_press_latch = 0;
+        self.new_key_presses = [false; 16];
+
+        self.tick_timers();
+
+        Ok(FrameOutcome {
+            redraw: self.take_draw_flag(),
+            play_sound: self.should_play_sound(),
+        })
+    }
+
+    // run_frame() with the keypad set to 'input' first, for hosts that poll their own input every frame
+    pub fn drive_frame(&mut self, cycles_per_frame: u32, input: &KeypadState) -> FrameResult {
+        self.set_keypad_state(input.mask());
+
+        self.run_frame(cycles_per_frame)
+    }
+
+    // Hands control to the host instead of owning a loop, 'frame_callback' gets the screen and whether to beep
+    // and returns the keys held for the next frame, or None to stop. It's called once before the first frame
+    pub fn drive<F>(&mut self, cycles_per_frame: u32, mut frame_callback: F) -> Result<(), InterpreterError>
+        where F: FnMut(&FrameBuffer, bool) -> Option<KeypadState> {
+        while let Some(input) = frame_callback(&self.frame_buffer, self.should_play_sound()) {
+            self.drive_frame(cycles_per_frame, &input)?;
+        }
+
+        Ok(())
+    }
+
+    // Breakpoints, write watches and waiting for the display all end the frame early
+    fn frame_interrupted(&mut self) -> bool {
+        if self.breakpoint_hit || self.write_watch_hit.is_some() {
+            return true;
+        }
+
+        std::mem::replace(&mut self.waiting_for_vblank, false)
+    }
+
+    fn get_x_from_opcode(&self) -> usize {
+        ((self.current_opcode & 0x0F00) >> 8) as usize
+    }
+
+    fn get_y_from_opcode(&self) -> usize {
+        ((self.current_opcode & 0x00F0) >> 4) as usize
+    }
+
+    fn get_nnn_from_opcode(&self) -> u16 {
+        self.current_opcode & 0x0FFF
+    }
+
+    fn get_nn_from_opcode(&self) -> u8 {
+        (self.current_opcode & 0x00FF) as u8
+    }
+
+    fn get_n_from_opcode(&self) -> u8 {
+        (&self.current_opcode & 0x000F) as u8
+    }
+
+    fn get_shift_source_from_opcode(&self) -> usize {
+        match self.shift_quirk {
+            ShiftQuirk::ShiftVy => self.get_y_from_opcode(),
+            ShiftQuirk::ShiftVx => self.get_x_from_opcode(),
+        }
+    }
+
+    // What Ex9E/ExA1 see for 'hex_key', depends on the key trigger quirk. Only the low nibble of Vx counts
+    fn key_down_for_skip(&self, hex_key: u8) -> bool {
+        let index = (hex_key & 0x0F) as usize;
+
+        match self.key_trigger_quirk {
+            KeyTriggerQuirk::Level => self.keypad_status[index],
+            KeyTriggerQuirk::Edge => self.new_key_presses[index],
+        }
+    }
+
+    // Fx0A's key once it's known, None while it still has to wait
+    fn wait_for_key(&mut self) -> Option<u8> {
+        if let Some(key) = self.wait_key_pressed {
+            if self.keypad_status[key as usize] {
+                return None;
+            }
+
+            self.wait_key_pressed = None;
+
+            return Some(key);
+        }
+
+        // Keys tapped and already released this frame count too
+        let pressed_keys = self.keypad_state() | self.key_press_latch;
+
+        if pressed_keys == 0 {
+            return None;
+        }
+
+        self.key_press_latch = 0;
+
+        // Lowest key wins when several are down, so the result doesn't depend on the order they went down in
+        let key = pressed_keys.trailing_zeros() as u8;
+
+        match self.wait_key_quirk {
+            WaitKeyQuirk::OnPress => Some(key),
+
+            WaitKeyQuirk::OnRelease => {
+                self.wait_key_pressed = Some(key);
+
+                // A tap may already be over
+                self.wait_for_key()
+            }
+        }
+    }
+
+    fn draw(&mut self, x: usize, y: usize, height: u8) {
+        // Starting position always wraps, only the sprite itself can be clipped
+        let (screen_width, screen_height) = self.frame_buffer.resolution();
+
+        let x_cord = self.general_registers[x] % screen_width;
+        let y_cord = self.general_registers[y] % screen_height;
+
+        let (clip_right, clip_bottom) = match (self.vip_draw_quirk, self.clip_quirk) {
+            (VipDrawQuirk::WrapRightClipBottom, _) => (false, true),
+            (VipDrawQuirk::Off, ClipQuirk::Clip) => (true, true),
+            (VipDrawQuirk::Off, ClipQuirk::Wrap) => (false, false),
+        };
+
+        let mut pixel_flipped = false;
+        // Only redraw when a pixel actually changed, an all zero sprite (or one fully clipped) leaves the screen alone
+        let mut screen_changed = false;
+
+        // XO-CHIP, every selected plane gets its own 'height' bytes of sprite data, one after the other
+        let planes: Vec<_> = self.frame_buffer.selected_plane_indices().collect();
+
+        for (plane_number, plane) in planes.into_iter().enumerate() {
+            let sprite_start = self.register_i as usize + plane_number * height as usize;
+
+            for diff_y in 0..height {
+                // Sprite rows past the end of memory wrap to 0x000 like the address bus would, ROMs that do
+                // this aren't treated as broken
+                let r = self.memory[self.wrap_address(sprite_start + diff_y as usize)];
+
+                for diff_x in 0..8 {
+                    let pixel_x = x_cord + diff_x;
+                    let pixel_y = y_cord + diff_y;
+
+                    if (clip_right && pixel_x >= screen_width) || (clip_bottom && pixel_y >= screen_height) {
+                        continue;
                     }
 
-                    _ => return Err(InvalidOpcode { pc: self.register_pc - 2, opcode: self.current_opcode })
+                    if r & (1 << (7 - diff_x)) != 0 {
+                        self.frame_buffer.flip_plane_pixel(plane, pixel_x, pixel_y);
+                        screen_changed = true;
+                        if !self.frame_buffer.get_plane_pixel(plane, pixel_x, pixel_y) {
+                            pixel_flipped = true;
+                        }
+                    }
                 }
             }
+        }
+
+        self.general_registers[0xF] = pixel_flipped as u8;
+        self.draw_flag |= screen_changed;
+        self.waiting_for_vblank = self.display_wait || self.timing_mode == TimingMode::VipCycles;
+    }
+
+    // PC already points past the bad opcode, so in lenient mode it is simply skipped
+    fn handle_invalid_opcode(&self) -> Result<u16, InterpreterError> {
+        let error = InvalidOpcode { pc: self.register_pc - 2, opcode: self.current_opcode };
+
+        if !self.lenient {
+            return Err(error);
+        }
+
+        eprintln!("{}, skipping", error);
+
+        Ok(self.current_opcode)
+    }
+
+    // Only informational, the instruction still runs as usual
+    fn warn_vf_operand(&mut self) {
+        let pc = self.register_pc - 2;
+
+        if self.vf_warning_pcs.insert(pc) {
+            eprintln!("Opcode={:#06x} at PC={:#04x} uses VF as an operand, the result may depend on the platform's quirks",
+                      self.current_opcode, pc);
+        }
+    }
+
+    // How many addresses -warn_vf has warned about so far
+    pub fn vf_warning_count(&self) -> usize {
+        self.vf_warning_pcs.len()
+    }
+
+    // A runaway recursion stops at the limit instead of growing the stack forever
+    fn fn_call(&mut self, address: u16) -> Result<(), InterpreterError> {
+        if self.stack.len() >= self.stack_limit {
+            return Err(StackOverflow { pc: self.register_pc - 2 });
+        }
+
+        self.stack.push(self.register_pc);
+        self.register_pc = address;
+
+        Ok(())
+    }
+
+    // With 64KB of memory the PC can run past 0xFFFF, that stops instead of wrapping to 0x0000
+    fn advance_pc(&mut self, bytes: u16) -> Result<(), InterpreterError> {
+        self.register_pc = self.register_pc.checked_add(bytes).ok_or(PcOutOfBounds { pc: self.register_pc })?;
 
-            _ => return Err(InvalidOpcode { pc: self.register_pc - 2, opcode: self.current_opcode })
+        Ok(())
+    }
+
+    // XO-CHIP F000 NNNN is 4 bytes long, skipping only its first word would run the address as an opcode
+    fn skip_next_instruction(&mut self) -> Result<(), InterpreterError> {
+        let pc = self.register_pc as usize;
+        let long_load_next = pc + 1 < self.memory.len() && self.memory[pc] == 0xF0 && self.memory[pc + 1] == 0x00;
+
+        self.advance_pc(if long_load_next { 4 } else { 2 })
+    }
+
+    fn fn_return(&mut self) -> Result<(), InterpreterError> {
+        match self.stack.pop() {
+            Some(val) => self.register_pc = val,
+            None => return Err(StackUnderflow { pc: self.register_pc })
         }
 
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    // Returns the opcode that was executed, when stopping at a breakpoint nothing runs and the
+    // opcode waiting at the breakpoint is returned instead (check breakpoint_hit() to tell them apart)
+    pub fn execute_next_instruction(&mut self) -> Result<u16, InterpreterError> {
+        if !self.rom_loaded {
+            return Err(NoRomLoaded);
+        }
 
-    #[test]
-    fn test_function_call() {
-        let mut interpreter = Chip8Interpreter::new(false);
-        let after_jump_pc = interpreter.register_pc;
+        // Both opcode bytes have to fit in memory
+        if self.register_pc as usize > self.memory.len() - 2 {
+            return Err(PcOutOfBounds { pc: self.register_pc });
+        }
 
-        interpreter.fn_call(0x2540);
-        interpreter.fn_return().unwrap();
+        let hi_byte = self.memory[self.register_pc as usize];
+        let lo_byte = self.memory[(self.register_pc + 1) as usize];
+        let opcode = (hi_byte as u16) << 8 | lo_byte as u16;
 
-        assert_eq!(after_jump_pc, interpreter.register_pc);
+        if self.breakpoint_hit {
+            self.breakpoint_hit = false;
+        } else if self.breakpoints.contains(&self.register_pc) {
+            self.breakpoint_hit = true;
+
+            return Ok(opcode);
+        }
+
+        self.write_watch_hit = None;
+        self.current_opcode = opcode;
+
+        if self.trace_enabled {
+            self.record_trace(self.register_pc, self.current_opcode);
+        }
+
+        if self.log_executions {
+            self.execution_log.push((self.register_pc, self.current_opcode));
+        }
+
+        if self.profile {
+            *self.opcode_counts.entry(Chip8Interpreter::opcode_category(opcode)).or_insert(0) += 1;
+        }
+
+        self.advance_pc(2)?;
+
+        match self.current_opcode & 0xF000 {
+            0x0000 => {
+                match self.current_opcode {
+                    0x00ee => self.fn_return()?,
+
+                    0x00e0 => self.draw_flag |= self.frame_buffer.clear(),
+
+                    // 00Cn (SCHIP) and 00Dn (XO-CHIP) scroll the selected planes by n rows
+                    _ if self.current_opcode & 0xFFF0 == 0x00C0 => {
+                        self.draw_flag |= self.frame_buffer.scroll_down((self.current_opcode & 0x000F) as usize);
+                    }
+
+                    _ if self.current_opcode & 0xFFF0 == 0x00D0 => {
+                        self.draw_flag |= self.frame_buffer.scroll_up((self.current_opcode & 0x000F) as usize);
+                    }
+
+                    // SCHIP scroll right (00FB) and left (00FC) by 4 pixels, at either resolution like Octo
+                    0x00fb => self.draw_flag |= self.frame_buffer.scroll_right(4),
+
+                    0x00fc => self.draw_flag |= self.frame_buffer.scroll_left(4),
+
+                    // SCHIP low (00FE) and high (00FF) resolution
+                    0x00fe | 0x00ff => {
+                        let old_resolution = self.frame_buffer.resolution();
+
+                        self.draw_flag |= self.frame_buffer.set_hires(self.current_opcode == 0x00ff);
+
+                        self.resolution_changed |= self.frame_buffer.resolution() != old_resolution;
+                    }
+
+                    // 0nnn (SYS) jumped into COSMAC VIP machine code, modern interpreters ignore it
+                    _ if self.legacy_sys => self.fn_call(self.get_nnn_from_opcode())?,
+
+                    _ => ()
+                }
+            }
+
+            0x1000 => self.register_pc = self.get_nnn_from_opcode(),
+
+            0x2000 => self.fn_call(self.get_nnn_from_opcode())?,
+
+            0x3000 => {
+                if self.general_registers[self.get_x_from_opcode()] == self.get_nn_from_opcode() {
+                    self.skip_next_instruction()?;
+                }
+            }
+
+            0x4000 => {
+                if self.general_registers[self.get_x_from_opcode()] != self.get_nn_from_opcode() {
+                    self.skip_next_instruction()?;
+                }
+            }
+
+            0x5000 => {
+                if self.general_registers[self.get_x_from_opcode()] == self.general_registers[self.get_y_from_opcode()] {
+                    self.skip_next_instruction()?;
+                }
+            }
+
+            0x6000 => self.general_registers[self.get_x_from_opcode()] = self.get_nn_from_opcode(),
+
+            0x7000 => {
+                let res = self.general_registers[self.get_x_from_opcode()].wrapping_add(self.get_nn_from_opcode());
+
+                self.general_registers[self.get_x_from_opcode()] = res;
+            }
+
+            // Every flag setting 8xyN op computes from the original operands and writes VF last,
+            // so with X = F the flag is what ends up in VF and the arithmetic result is discarded
+            0x8000 => {
+                let uses_vf = self.get_x_from_opcode() == 0xF || self.get_y_from_opcode() == 0xF;
+
+                if self.warn_vf && uses_vf && matches!(self.current_opcode & 0x000F, 0x0..=0x7 | 0xE) {
+                    self.warn_vf_operand();
+                }
+
+                match self.current_opcode & 0x000F {
+                    0x0 => self.general_registers[self.get_x_from_opcode()] = self.general_registers[self.get_y_from_opcode()],
+
+                    0x1 => self.general_registers[self.get_x_from_opcode()] |= self.general_registers[self.get_y_from_opcode()],
+
+                    0x2 => self.general_registers[self.get_x_from_opcode()] &= self.general_registers[self.get_y_from_opcode()],
+
+                    0x3 => self.general_registers[self.get_x_from_opcode()] ^= self.general_registers[self.get_y_from_opcode()],
+
+                    0x4 => {
+                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+                        let reg_y = self.general_registers[self.get_y_from_opcode()];
+
+                        let result = reg_x as u16 + reg_y as u16;
+
+                        self.general_registers[self.get_x_from_opcode()] = result as u8;
+                        self.general_registers[0xF] = (result > 0xFF) as u8;
+                    }
+
+                    // VF = 1 when there is no borrow
+                    0x5 => {
+                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+                        let reg_y = self.general_registers[self.get_y_from_opcode()];
+
+                        self.general_registers[self.get_x_from_opcode()] = reg_x.wrapping_sub(reg_y);
+                        self.general_registers[0xF] = (reg_x >= reg_y) as u8;
+                    }
+
+                    0x6 => {
+                        let source = self.general_registers[self.get_shift_source_from_opcode()];
+
+                        self.general_registers[self.get_x_from_opcode()] = source >> 1;
+                        self.general_registers[0xF] = source & 1;
+                    }
+
+                    0x7 => {
+                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+                        let reg_y = self.general_registers[self.get_y_from_opcode()];
+
+                        self.general_registers[self.get_x_from_opcode()] = reg_y.wrapping_sub(reg_x);
+                        self.general_registers[0xF] = (reg_y >= reg_x) as u8;
+                    }
+
+                    0xE => {
+                        let source = self.general_registers[self.get_shift_source_from_opcode()];
+
+                        self.general_registers[self.get_x_from_opcode()] = source << 1;
+                        self.general_registers[0xF] = source >> 7;
+                    }
+
+                    _ => return self.handle_invalid_opcode()
+                }
+            }
+
+            0x9000 => {
+                if self.general_registers[self.get_x_from_opcode()] != self.general_registers[self.get_y_from_opcode()] {
+                    self.skip_next_instruction()?;
+                }
+            }
+
+            0xA000 => self.register_i = self.get_nnn_from_opcode(),
+
+            0xB000 => self.register_pc = self.get_nnn_from_opcode().wrapping_add(self.general_registers[0] as u16),
+
+            0xC000 => {
+                let random_byte = self.rng.gen::<u8>() & self.get_nn_from_opcode();
+
+                self.general_registers[self.get_x_from_opcode()] = random_byte;
+            }
+
+            0xD000 => self.draw(self.get_x_from_opcode(), self.get_y_from_opcode(), self.get_n_from_opcode()),
+
+            0xE000 => {
+                match self.current_opcode & 0x00FF {
+                    0x9E => {
+                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+
+                        if self.key_down_for_skip(reg_x) {
+                            self.skip_next_instruction()?;
+                        }
+                    }
+
+                    0xA1 => {
+                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+
+                        if !self.key_down_for_skip(reg_x) {
+                            self.skip_next_instruction()?;
+                        }
+                    }
+
+                    _ => return self.handle_invalid_opcode()
+                }
+            }
+
+            0xF000 => {
+                match self.current_opcode & 0x00FF {
+                    // XO-CHIP long load, the address is the word following the opcode
+                    0x00 if self.current_opcode == 0xF000 => {
+                        if self.register_pc as usize > self.memory.len() - 2 {
+                            return Err(PcOutOfBounds { pc: self.register_pc });
+                        }
+
+                        let hi_byte = self.memory[self.register_pc as usize];
+                        let lo_byte = self.memory[self.register_pc as usize + 1];
+
+                        self.register_i = (hi_byte as u16) << 8 | lo_byte as u16;
+                        self.advance_pc(2)?;
+                    }
+
+                    // XO-CHIP, selects the planes (bit mask x) later draws, clears and scrolls work on
+                    0x01 => self.frame_buffer.select_planes(self.get_x_from_opcode() as u8),
+
+                    0x07 => self.general_registers[self.get_x_from_opcode()] = self.delay_timer,
+
+                    0x0A => {
+                        match self.wait_for_key() {
+                            Some(key) => self.general_registers[self.get_x_from_opcode()] = key,
+
+                            // Stay on this instruction until a key is in
+                            None => self.register_pc -= 2
+                        }
+                    }
+
+                    0x15 => self.delay_timer = self.general_registers[self.get_x_from_opcode()],
+
+                    0x18 => self.sound_timer = self.general_registers[self.get_x_from_opcode()],
+
+                    0x1E => {
+                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+
+                        self.register_i = self.register_i.wrapping_add(reg_x as u16);
+                    }
+
+                    0x29 => {
+                        let digit = (self.general_registers[self.get_x_from_opcode()] & 0x0F) as u16;
+
+                        self.register_i = FONT_START_ADDRESS + digit * 5;
+                    }
+
+                    0x30 => {
+                        let digit = (self.general_registers[self.get_x_from_opcode()] & 0x0F) as u16;
+
+                        self.register_i = BIG_FONT_START_ADDRESS + digit * 10;
+                    }
+
+                    0x33 => {
+                        let reg_x = self.general_registers[self.get_x_from_opcode()];
+
+                        let digits = [reg_x / 100, (reg_x / 10) % 10, reg_x % 10];
+
+                        for (i, digit) in digits.into_iter().enumerate() {
+                            self.write_memory(self.wrap_address(self.register_i as usize + i), digit);
+                        }
+                    }
+
+                    0x55 => {
+                        let end_index = self.get_x_from_opcode();
+
+                        for i in 0..end_index + 1 {
+                            self.write_memory(self.wrap_address(self.register_i as usize + i), self.general_registers[i]);
+                        }
+
+                        if self.memory_quirk == MemoryQuirk::IncrementI {
+                            self.register_i = self.register_i.wrapping_add(end_index as u16 + 1);
+                        }
+                    }
+
+                    0x65 => {
+                        for i in 0..=self.get_x_from_opcode() {
+                            self.general_registers[i] = self.memory[self.wrap_address(self.register_i as usize + i)];
+                        }
+
+                        if self.memory_quirk == MemoryQuirk::IncrementI {
+                            self.register_i = self.register_i.wrapping_add(self.get_x_from_opcode() as u16 + 1);
+                        }
+                    }
+
+                    0x75 => {
+                        let count = self.get_x_from_opcode() + 1;
+
+                        self.rpl_flags[..count].copy_from_slice(&self.general_registers[..count]);
+                    }
+
+                    0x85 => {
+                        let count = self.get_x_from_opcode() + 1;
+
+                        self.general_registers[..count].copy_from_slice(&self.rpl_flags[..count]);
+                    }
+
+                    _ => return self.handle_invalid_opcode()
+                }
+            }
+
+            _ => return self.handle_invalid_opcode()
+        }
+
+        Ok(self.current_opcode)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_function_call() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+        let after_jump_pc = interpreter.register_pc;
+
+        interpreter.fn_call(0x2540).unwrap();
+        interpreter.fn_return().unwrap();
+
+        assert_eq!(after_jump_pc, interpreter.register_pc);
+    }
+
+    // Opcodes from 0x200 on, big endian like in a ROM file
+    fn load_program(program: &[u16]) -> Chip8Interpreter {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+        let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+
+        interpreter.load_rom_bytes(&bytes).unwrap();
+
+        interpreter
+    }
+
+    #[test]
+    fn test_current_opcode_fields() {
+        // V3 += V4, then V1 = 0x2A
+        let mut interpreter = load_program(&[0x8344, 0x612A]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.current_opcode(), 0x8344);
+        assert_eq!((interpreter.opcode_x(), interpreter.opcode_y(), interpreter.opcode_n()), (3, 4, 4));
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.current_opcode(), 0x612A);
+        assert_eq!(interpreter.opcode_nn(), 0x2A);
+        assert_eq!(interpreter.opcode_nnn(), 0x12A);
+    }
+
+    #[test]
+    fn test_opcode_clear_screen() {
+        let mut interpreter = load_program(&[0x00E0]);
+
+        interpreter.frame_buffer.flip_pixel(3, 4);
+        interpreter.execute_next_instruction().unwrap();
+
+        assert!(!interpreter.frame_buffer.get_pixel(3, 4));
+        assert!(interpreter.take_draw_flag());
+    }
+
+    #[test]
+    fn test_opcode_call_and_return() {
+        // 0x200: call 0x204, 0x202: (return address), 0x204: return
+        let mut interpreter = load_program(&[0x2204, 0x0000, 0x00EE]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x204);
+        assert_eq!(interpreter.stack, vec![0x202]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert!(interpreter.stack.is_empty());
+    }
+
+    #[test]
+    fn test_opcode_jumps() {
+        let mut interpreter = load_program(&[0x1234]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x234);
+
+        // Bnnn adds V0
+        let mut interpreter = load_program(&[0xB300]);
+
+        interpreter.general_registers[0] = 0x04;
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x304);
+    }
+
+    #[test]
+    fn test_opcode_skips() {
+        // (opcode, V1, V2, skipped)
+        let cases = [
+            (0x3105, 5, 0, true),
+            (0x3105, 6, 0, false),
+            (0x4105, 5, 0, false),
+            (0x4105, 6, 0, true),
+            (0x5120, 7, 7, true),
+            (0x5120, 7, 8, false),
+            (0x9120, 7, 7, false),
+            (0x9120, 7, 8, true),
+        ];
+
+        for (opcode, reg_1, reg_2, skipped) in cases {
+            let mut interpreter = load_program(&[opcode]);
+
+            interpreter.general_registers[1] = reg_1;
+            interpreter.general_registers[2] = reg_2;
+            interpreter.execute_next_instruction().unwrap();
+
+            assert_eq!(interpreter.register_pc, if skipped { 0x204 } else { 0x202 }, "{:#06x}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_opcode_load_and_add_immediate() {
+        // VA = 0x42, VA += 0xFF
+        let mut interpreter = load_program(&[0x6A42, 0x7AFF]);
+
+        interpreter.general_registers[0xF] = 7;
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.general_registers[0xA], 0x42);
+
+        // Wraps around without touching VF
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.general_registers[0xA], 0x41);
+        assert_eq!(interpreter.general_registers[0xF], 7);
+    }
+
+    #[test]
+    fn test_opcode_register_logic() {
+        assert_eq!(run_alu(0x8120, 0b1010, 0b0110), (0b0110, 0));
+        assert_eq!(run_alu(0x8121, 0b1010, 0b0110), (0b1110, 0));
+        assert_eq!(run_alu(0x8122, 0b1010, 0b0110), (0b0010, 0));
+        assert_eq!(run_alu(0x8123, 0b1010, 0b0110), (0b1100, 0));
+    }
+
+    #[test]
+    fn test_opcode_set_i() {
+        // I = 0x123, I += V0
+        let mut interpreter = load_program(&[0xA123, 0xF01E]);
+
+        interpreter.general_registers[0] = 0x10;
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_i, 0x123);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_i, 0x133);
+    }
+
+    #[test]
+    fn test_opcode_random_is_masked() {
+        // V0 = rand & 0x0F, V1 = rand & 0x00
+        let mut interpreter = load_program(&[0xC00F, 0xC100]);
+
+        interpreter.general_registers[1] = 0xAA;
+
+        interpreter.execute_next_instruction().unwrap();
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.general_registers[0] & 0xF0, 0);
+        assert_eq!(interpreter.general_registers[1], 0);
+    }
+
+    #[test]
+    fn test_opcode_draw_collision() {
+        // Font '0' at (V0, V1) twice, the second draw erases the first
+        let mut interpreter = load_program(&[0xD015, 0xD015]);
+
+        interpreter.register_i = FONT_START_ADDRESS;
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.general_registers[0xF], 0);
+        assert!(interpreter.frame_buffer.get_pixel(0, 0));
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.general_registers[0xF], 1);
+        assert_eq!(interpreter.frame_buffer.to_ascii(), FrameBuffer::new().to_ascii());
+    }
+
+    #[test]
+    fn test_opcode_timers() {
+        // delay = V3, sound = V3, V4 = delay
+        let mut interpreter = load_program(&[0xF315, 0xF318, 0xF407]);
+
+        interpreter.general_registers[3] = 30;
+
+        for _ in 0..3 {
+            interpreter.execute_next_instruction().unwrap();
+        }
+
+        assert_eq!(interpreter.delay_timer(), 30);
+        assert_eq!(interpreter.sound_timer(), 30);
+        assert_eq!(interpreter.general_registers[4], 30);
+    }
+
+    #[test]
+    fn test_opcode_store_and_load_registers_range() {
+        // Store V0-V2 at I, then load them back into V0-V2
+        let mut interpreter = load_program(&[0xF255, 0xF265]);
+
+        interpreter.register_i = 0x300;
+        interpreter.general_registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.memory_slice(0x300, 4), &[1, 2, 3, 0]);
+        assert_eq!(interpreter.register_i, 0x300);
+
+        interpreter.general_registers[..4].copy_from_slice(&[0, 0, 0, 9]);
+        interpreter.memory[0x303] = 0xEE;
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.general_registers[..4], [1, 2, 3, 9]);
+        assert_eq!(interpreter.register_i, 0x300);
+    }
+
+    #[test]
+    fn test_opcode_invalid_arms() {
+        for opcode in [0x8008, 0x800F, 0xE0FF, 0xF0FF] {
+            let mut interpreter = load_program(&[opcode]);
+
+            assert!(matches!(interpreter.execute_next_instruction(), Err(InvalidOpcode { pc: 0x200, .. })), "{:#06x}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_tick_timers_stops_at_zero() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.set_delay_timer(2);
+        interpreter.set_sound_timer(2);
+
+        interpreter.tick_timers();
+        assert_eq!(interpreter.delay_timer(), 1);
+        assert_eq!(interpreter.sound_timer(), 1);
+
+        interpreter.tick_timers();
+        interpreter.tick_timers();
+        assert_eq!(interpreter.delay_timer(), 0);
+        assert_eq!(interpreter.sound_timer(), 0);
+    }
+
+    #[test]
+    fn test_tick_timers_counts_down_in_n_ticks() {
+        for n in [1u8, 60, 255] {
+            let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+            interpreter.set_delay_timer(n);
+            interpreter.set_sound_timer(n);
+
+            for tick in 1..=n {
+                assert!(interpreter.should_play_sound());
+
+                interpreter.tick_timers();
+                assert_eq!(interpreter.delay_timer(), n - tick);
+                assert_eq!(interpreter.sound_timer(), n - tick);
+            }
+
+            assert!(!interpreter.should_play_sound());
+
+            // No underflow past zero
+            interpreter.tick_timers();
+            assert_eq!(interpreter.delay_timer(), 0);
+            assert_eq!(interpreter.sound_timer(), 0);
+            assert!(!interpreter.should_play_sound());
+        }
+    }
+
+    #[test]
+    fn test_should_play_sound_needs_timer_and_sound() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { sound_enabled: false, ..Chip8Config::default() });
+
+        assert!(!interpreter.should_play_sound());
+
+        interpreter.set_sound_timer(5);
+        assert!(!interpreter.should_play_sound());
+
+        interpreter.set_sound_enabled(true);
+        assert!(interpreter.should_play_sound());
+
+        interpreter.set_sound_timer(0);
+        assert!(!interpreter.should_play_sound());
+    }
+
+    #[test]
+    fn test_toggle_sound() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.sound_timer = 10;
+        assert!(interpreter.should_play_sound());
+
+        interpreter.toggle_sound();
+        assert!(!interpreter.should_play_sound());
+
+        interpreter.toggle_sound();
+        assert!(interpreter.should_play_sound());
+
+        interpreter.set_sound_enabled(false);
+        assert!(!interpreter.should_play_sound());
+    }
+
+    #[test]
+    fn test_vip_cycle_cost() {
+        assert_eq!(Chip8Interpreter::vip_cycle_cost(0x00E0), 24);
+        assert_eq!(Chip8Interpreter::vip_cycle_cost(0x6A05), 6);
+        assert_eq!(Chip8Interpreter::vip_cycle_cost(0x8124), 44);
+        assert_eq!(Chip8Interpreter::vip_cycle_cost(0x8120), 12);
+        assert_eq!(Chip8Interpreter::vip_cycle_cost(0xD125), 68);
+        assert_eq!(Chip8Interpreter::vip_cycle_cost(0xF333), 84);
+
+        // Not a VIP instruction
+        assert_eq!(Chip8Interpreter::vip_cycle_cost(0x00FF), VIP_DEFAULT_CYCLE_COST);
+    }
+
+    #[test]
+    fn test_run_frame_vip_cycles() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { timing_mode: TimingMode::VipCycles, ..Chip8Config::default() });
+
+        // 0x200: V1 += 1, 0x202: jump to 0x200, 22 cycles per loop
+        interpreter.load_rom_bytes(&[0x71, 0x01, 0x12, 0x00]).unwrap();
+
+        // The instruction count is ignored, 3668 / 22 = 166.7 so the 167th add runs over into the next frame
+        interpreter.run_frame(1).unwrap();
+        assert_eq!(interpreter.general_registers[1], 167);
+
+        // 6 cycles owed, the frame ends right after its 167th add
+        interpreter.general_registers[1] = 0;
+        interpreter.run_frame(1).unwrap();
+        assert_eq!(interpreter.general_registers[1], 167);
+        assert_eq!(interpreter.register_pc, 0x202);
+
+        // A draw waits for the display, the rest of the frame is idle
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { timing_mode: TimingMode::VipCycles, ..Chip8Config::default() });
+
+        // 0x200: draw, 0x202: V1 += 1, 0x204: jump to 0x202
+        interpreter.load_rom_bytes(&[0xD0, 0x01, 0x71, 0x01, 0x12, 0x02]).unwrap();
+
+        interpreter.run_frame(1).unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+    }
+
+    #[test]
+    fn test_drive() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: V0 = key, 0x202: I = font V0, 0x204: draw it at (V0, V0), 0x206: jump to 0x206
+        interpreter.load_rom_bytes(&[0xF0, 0x0A, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+
+        let mut lit_per_frame = vec![];
+
+        interpreter.drive(10, |frame_buffer, play_sound| {
+            assert!(!play_sound);
+            lit_per_frame.push(frame_buffer.get_pixel(8, 8));
+
+            // Key 8 held during the third frame only
+            let mut input = KeypadState::default();
+            input.set(0x8, lit_per_frame.len() == 3);
+
+            (lit_per_frame.len() < 6).then_some(input)
+        }).unwrap();
+
+        assert_eq!(lit_per_frame, vec![false, false, false, true, true, true]);
+        assert_eq!(interpreter.general_registers[0], 0x8);
+        assert_eq!(interpreter.keypad_state(), 0);
+    }
+
+    #[test]
+    fn test_clear_keys() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.set_keypad_state(0xFFFF);
+        interpreter.clear_keys();
+
+        assert!((0..16).all(|hex_key| !interpreter.key_state(hex_key)));
+        assert_eq!(interpreter.keypad_state(), 0);
+    }
+
+    #[test]
+    fn test_keypad_state() {
+        let mut input = KeypadState::default();
+
+        input.set(0x3, true);
+        input.set(0xF, true);
+        input.set(0x10, true);
+        input.set(0x3, false);
+
+        assert_eq!(input, KeypadState::from_mask(0x8000));
+        assert!(input.is_pressed(0xF));
+        assert!(!input.is_pressed(0x3));
+        assert!(!input.is_pressed(0x10));
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: V0 = 0x05, 0x202: ST = V0, 0x204: CLS, 0x206: jump to 0x206
+        let program = [0x60, 0x05, 0xF0, 0x18, 0x00, 0xE0, 0x12, 0x06];
+        interpreter.load_rom_bytes(&program).unwrap();
+        // CLS only redraws when something was lit
+        interpreter.frame_buffer.flip_pixel(0, 0);
+
+        let outcome = interpreter.run_frame(2).unwrap();
+        assert_eq!(outcome, FrameOutcome { redraw: false, play_sound: true });
+        assert_eq!(interpreter.sound_timer, 4);
+
+        let outcome = interpreter.run_frame(2).unwrap();
+        assert_eq!(outcome, FrameOutcome { redraw: true, play_sound: true });
+
+        for _ in 0..3 {
+            interpreter.run_frame(2).unwrap();
+        }
+
+        let outcome = interpreter.run_frame(2).unwrap();
+        assert_eq!(outcome, FrameOutcome { redraw: false, play_sound: false });
+        assert_eq!(interpreter.register_pc, 0x206);
+    }
+
+    #[test]
+    fn test_trace_records_executed_instructions() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        let program = [0x60, 0x01, 0x61, 0x02, 0x62, 0x03];
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.set_trace_enabled(true);
+
+        for _ in 0..3 {
+            interpreter.execute_next_instruction().unwrap();
+        }
+
+        assert_eq!(interpreter.recent_trace(), &[(0x200, 0x6001), (0x202, 0x6102), (0x204, 0x6203)]);
+    }
+
+    #[test]
+    fn test_trace_is_bounded() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: v0 += 1, 0x202: jump to 0x200
+        interpreter.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        interpreter.set_trace_enabled(true);
+
+        for _ in 0..TRACE_CAPACITY + 11 {
+            interpreter.execute_next_instruction().unwrap();
+        }
+
+        assert_eq!(interpreter.recent_trace().len(), TRACE_CAPACITY);
+        // The oldest entries were dropped, the newest one is still last
+        assert_eq!(interpreter.recent_trace().front(), Some(&(0x202, 0x1200)));
+        assert_eq!(interpreter.recent_trace().back(), Some(&(0x200, 0x7001)));
+    }
+
+    #[test]
+    fn test_execution_log_drains() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { log_executions: true, ..Chip8Config::default() });
+
+        // 0x200: jump to 0x200
+        interpreter.load_rom_bytes(&[0x12, 0x00]).unwrap();
+
+        interpreter.run_frame(TRACE_CAPACITY as u32 + 10).unwrap();
+
+        assert_eq!(interpreter.drain_execution_log().count(), TRACE_CAPACITY + 10);
+        assert_eq!(interpreter.drain_execution_log().count(), 0);
+    }
+
+    #[test]
+    fn test_opcode_counts() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { profile: true, ..Chip8Config::default() });
+
+        // 6001, 6102, 8014, 8124, 00E0, F029
+        interpreter.load_rom_bytes(&[0x60, 0x01, 0x61, 0x02, 0x80, 0x14, 0x81, 0x24, 0x00, 0xE0, 0xF0, 0x29]).unwrap();
+
+        for _ in 0..6 {
+            interpreter.execute_next_instruction().unwrap();
+        }
+
+        let counts = interpreter.opcode_counts();
+
+        assert_eq!(counts.len(), 4);
+        assert_eq!(counts[&0x6000], 2);
+        assert_eq!(counts[&0x8004], 2);
+        assert_eq!(counts[&0x00E0], 1);
+        assert_eq!(counts[&0xF029], 1);
+    }
+
+    #[test]
+    fn test_opcode_counts_off_by_default() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.load_rom_bytes(&[0x60, 0x01]).unwrap();
+        interpreter.execute_next_instruction().unwrap();
+
+        assert!(interpreter.opcode_counts().is_empty());
+    }
+
+    #[test]
+    fn test_opcode_category() {
+        assert_eq!(Chip8Interpreter::opcode_category(0x00EE), 0x00EE);
+        assert_eq!(Chip8Interpreter::opcode_category(0x0123), 0x0000);
+        assert_eq!(Chip8Interpreter::opcode_category(0x00D4), 0x00D0);
+        assert_eq!(Chip8Interpreter::opcode_category(0x1ABC), 0x1000);
+        assert_eq!(Chip8Interpreter::opcode_category(0x8ABE), 0x800E);
+        assert_eq!(Chip8Interpreter::opcode_category(0xE3A1), 0xE0A1);
+        assert_eq!(Chip8Interpreter::opcode_category(0xF000), 0xF000);
+    }
+
+    #[test]
+    fn test_breakpoint_stops_before_execution() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        let program = [0x60, 0x01, 0x61, 0x02, 0x62, 0x03];
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.add_breakpoint(0x202);
+
+        assert_eq!(interpreter.execute_next_instruction().unwrap(), 0x6001);
+        assert!(!interpreter.breakpoint_hit());
+
+        // The opcode waiting at the breakpoint is reported without running it
+        assert_eq!(interpreter.execute_next_instruction().unwrap(), 0x6102);
+        assert!(interpreter.breakpoint_hit());
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert_eq!(interpreter.general_registers[1], 0x00);
+
+        // Stepping again resumes past the breakpoint
+        assert_eq!(interpreter.execute_next_instruction().unwrap(), 0x6102);
+        assert!(!interpreter.breakpoint_hit());
+        assert_eq!(interpreter.register_pc, 0x204);
+        assert_eq!(interpreter.general_registers[1], 0x02);
+
+        interpreter.remove_breakpoint(0x202);
+        interpreter.register_pc = 0x202;
+
+        interpreter.execute_next_instruction().unwrap();
+        assert!(!interpreter.breakpoint_hit());
+    }
+
+    #[test]
+    fn test_run_frame_stops_at_breakpoint() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        let program = [0x60, 0x01, 0x61, 0x02, 0x62, 0x03];
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.add_breakpoint(0x204);
+        interpreter.run_frame(10).unwrap();
+
+        assert!(interpreter.breakpoint_hit());
+        assert_eq!(interpreter.register_pc, 0x204);
+    }
+
+    #[test]
+    fn test_write_watch_triggers_on_bcd() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: I = 0x300, 0x202: BCD of V0 at I
+        let program = [0xA3, 0x00, 0xF0, 0x33];
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.add_write_watch(0x301, 0x301);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.write_watch_hit(), None);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.write_watch_hit(), Some(0x301));
+    }
+
+    #[test]
+    fn test_write_watch_ignores_unwatched_bcd() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        let program = [0xA3, 0x00, 0xF0, 0x33];
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.add_write_watch(0x303, 0x310);
+
+        interpreter.execute_next_instruction().unwrap();
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.write_watch_hit(), None);
+    }
+
+    #[test]
+    fn test_write_watch_triggers_on_register_dump() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: I = 0x300, 0x202: store V0..V3 at I
+        let program = [0xA3, 0x00, 0xF3, 0x55];
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.add_write_watch(0x302, 0x3FF);
+        interpreter.run_frame(10).unwrap();
+
+        assert_eq!(interpreter.write_watch_hit(), Some(0x302));
+        assert_eq!(interpreter.register_pc, 0x204);
+    }
+
+    #[test]
+    fn test_memory_slice_reads_font() {
+        let interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        assert_eq!(interpreter.memory_slice(0x050, 5), &C8_FONT_SET[0..5]);
+        assert_eq!(interpreter.memory_slice(0x050, C8_FONT_SET.len() as u16), &C8_FONT_SET[..]);
+    }
+
+    #[test]
+    fn test_memory_slice_clamps_to_memory_size() {
+        let interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        assert_eq!(interpreter.memory_slice(4090, 100).len(), 6);
+        assert!(interpreter.memory_slice(5000, 10).is_empty());
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { stack_limit: COSMAC_VIP_STACK_LIMIT, ..Chip8Config::default() });
+
+        // 0x200: call 0x200, recurses forever
+        interpreter.load_rom_bytes(&[0x22, 0x00]).unwrap();
+
+        for _ in 0..COSMAC_VIP_STACK_LIMIT {
+            interpreter.execute_next_instruction().unwrap();
+        }
+
+        assert!(matches!(interpreter.execute_next_instruction(), Err(StackOverflow { pc: 0x200 })));
+        assert_eq!(interpreter.stack_slice().len(), COSMAC_VIP_STACK_LIMIT);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_state_json() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.general_registers[0xA] = 0x42;
+        interpreter.register_i = 0x300;
+        interpreter.delay_timer = 7;
+        interpreter.fn_call(0x400).unwrap();
+
+        let state: serde_json::Value = serde_json::from_str(&interpreter.state_json(false)).unwrap();
+
+        assert_eq!(state["pc"], 0x400);
+        assert_eq!(state["i"], 0x300);
+        assert_eq!(state["sp"], 1);
+        assert_eq!(state["registers"][0xA], 0x42);
+        assert_eq!(state["stack"][0], 0x200);
+        assert_eq!(state["delay_timer"], 7);
+        assert_eq!(state["sound_timer"], 0);
+        assert!(state.get("memory").is_none());
+
+        interpreter.frame_buffer.flip_pixel(1, 0);
+
+        let state: serde_json::Value = serde_json::from_str(&interpreter.state_json(true)).unwrap();
+
+        assert_eq!(state["memory"].as_array().unwrap().len(), DEFAULT_MEMORY_SIZE);
+        assert_eq!(state["frame_buffer"].as_array().unwrap().len(), 32);
+        assert!(state["frame_buffer"][0].as_str().unwrap().starts_with("010"));
+    }
+
+    #[test]
+    fn test_stack_slice() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.fn_call(0x300).unwrap();
+        interpreter.fn_call(0x400).unwrap();
+
+        assert_eq!(interpreter.stack_slice(), &[0x200, 0x300]);
+    }
+
+    #[test]
+    fn test_pc_out_of_bounds() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.load_rom_bytes(&[]).unwrap();
+        interpreter.register_pc = 4095;
+
+        assert!(matches!(interpreter.execute_next_instruction(), Err(PcOutOfBounds { pc: 4095 })));
+
+        // Last full opcode in memory is still fetched, 0x1FFE = jump to 0xFFE
+        interpreter.register_pc = 4094;
+        interpreter.memory[4094] = 0x1F;
+        interpreter.memory[4095] = 0xFE;
+
+        assert!(matches!(interpreter.execute_next_instruction(), Ok(0x1FFE)));
+    }
+
+    #[test]
+    fn test_pc_stops_at_end_of_64kb_memory() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { memory_size: XO_CHIP_MEMORY_SIZE, ..Chip8Config::default() });
+
+        interpreter.load_rom_bytes(&[]).unwrap();
+
+        // 0xFFFE: v0 = 5, the PC after it doesn't fit in 16 bits
+        interpreter.register_pc = 0xFFFE;
+        interpreter.memory[0xFFFE] = 0x60;
+        interpreter.memory[0xFFFF] = 0x05;
+
+        assert!(matches!(interpreter.execute_next_instruction(), Err(PcOutOfBounds { pc: 0xFFFE })));
+        assert_eq!(interpreter.general_registers[0], 0);
+
+        // 0xFFFC: skip if v0 == 0, skipping 0xFFFE runs off the end too
+        interpreter.register_pc = 0xFFFC;
+        interpreter.memory[0xFFFC] = 0x30;
+        interpreter.memory[0xFFFD] = 0x00;
+
+        assert!(matches!(interpreter.execute_next_instruction(), Err(PcOutOfBounds { pc: 0xFFFE })));
+
+        // Not skipping is fine, 0xFFFE is still fetched
+        interpreter.register_pc = 0xFFFC;
+        interpreter.memory[0xFFFD] = 0x01;
+
+        assert!(matches!(interpreter.execute_next_instruction(), Ok(0x3001)));
+        assert_eq!(interpreter.pc(), 0xFFFE);
+    }
+
+    #[test]
+    fn test_execute_without_rom() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        assert!(!interpreter.is_rom_loaded());
+        assert!(matches!(interpreter.execute_next_instruction(), Err(NoRomLoaded)));
+        assert!(matches!(interpreter.run_frame(10), Err(NoRomLoaded)));
+        assert_eq!(interpreter.pc(), 0x200);
+
+        // An empty ROM is still a ROM
+        interpreter.load_rom_bytes(&[]).unwrap();
+
+        assert!(interpreter.is_rom_loaded());
+        assert!(interpreter.execute_next_instruction().is_ok());
+    }
+
+    #[test]
+    fn test_shift_quirk() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: V0 >>= 1 / V0 = V1 >> 1
+        interpreter.load_rom_bytes(&[0x80, 0x16]).unwrap();
+
+        interpreter.general_registers[0] = 0b0100;
+        interpreter.general_registers[1] = 0b0011;
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.general_registers[0], 0b0010);
+        assert_eq!(interpreter.general_registers[0xF], 0);
+
+        interpreter.set_shift_quirk(ShiftQuirk::ShiftVy);
+        interpreter.register_pc = 0x200;
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.general_registers[0], 0b0001);
+        assert_eq!(interpreter.general_registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_memory_quirk() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: I = 0x300, 0x202: store V0..V2, 0x204: load V0..V2
+        let program = [0xA3, 0x00, 0xF2, 0x55, 0xF2, 0x65];
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.run_frame(3).unwrap();
+        assert_eq!(interpreter.register_i, 0x300);
+
+        interpreter.set_memory_quirk(MemoryQuirk::IncrementI);
+        interpreter.register_pc = 0x200;
+        interpreter.run_frame(3).unwrap();
+        assert_eq!(interpreter.register_i, 0x306);
+    }
+
+    #[test]
+    fn test_display_wait_ends_frame_after_draw() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: draw, 0x202: draw
+        let program = [0xD0, 0x01, 0xD0, 0x01];
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.run_frame(2).unwrap();
+        assert_eq!(interpreter.register_pc, 0x204);
+
+        interpreter.set_display_wait(true);
+        interpreter.register_pc = 0x200;
+
+        interpreter.run_frame(2).unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+
+        interpreter.run_frame(2).unwrap();
+        assert_eq!(interpreter.register_pc, 0x204);
+    }
+
+    #[test]
+    fn test_set_key() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.set_key(0xA, true);
+        interpreter.set_key(0x3, true);
+        assert!(interpreter.key_state(0xA));
+        assert!(interpreter.key_state(0x3));
+        assert!(!interpreter.key_state(0x0));
+
+        interpreter.set_key(0xA, false);
+        assert!(!interpreter.key_state(0xA));
+        assert!(interpreter.key_state(0x3));
+
+        // Out of range keys are ignored
+        interpreter.set_key(0x10, true);
+        assert!(!interpreter.key_state(0x10));
+    }
+
+    #[test]
+    fn test_skip_on_key() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: skip if key V0 pressed, 0x204: skip if key V0 not pressed
+        let program = [0xE0, 0x9E, 0x00, 0x00, 0xE0, 0xA1];
+        interpreter.load_rom_bytes(&program).unwrap();
+        interpreter.general_registers[0] = 0x7;
+
+        interpreter.set_key(0x7, true);
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x204);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x206);
+
+        interpreter.set_key(0x7, false);
+        interpreter.register_pc = 0x204;
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x208);
+    }
+
+    #[test]
+    fn test_skip_on_key_uses_low_nibble() {
+        for key_trigger_quirk in [KeyTriggerQuirk::Level, KeyTriggerQuirk::Edge] {
+            let mut interpreter = Chip8Interpreter::new(Chip8Config { key_trigger_quirk, ..Chip8Config::default() });
+
+            // 0x200: skip if key V0 pressed, 0x204: skip if key V0 not pressed
+            interpreter.load_rom_bytes(&[0xE0, 0x9E, 0x00, 0x00, 0xE0, 0xA1]).unwrap();
+            interpreter.general_registers[0] = 0x1F;
+
+            interpreter.set_key(0xF, true);
+            interpreter.execute_next_instruction().unwrap();
+            assert_eq!(interpreter.register_pc, 0x204);
+
+            interpreter.execute_next_instruction().unwrap();
+            assert_eq!(interpreter.register_pc, 0x206);
+        }
+    }
+
+    // Runs 'frames' frames of 'Ex9E, V1 += 1, jump to 0x200' with key 0x7 held the whole time, V1 counts the skips that
+    // didn't happen
+    fn count_unskipped_with_key_held(key_trigger_quirk: KeyTriggerQuirk, frames: u32) -> u8 {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { key_trigger_quirk, ..Chip8Config::default() });
+
+        interpreter.load_rom_bytes(&[0xE0, 0x9E, 0x71, 0x01, 0x12, 0x00]).unwrap();
+        interpreter.general_registers[0] = 0x7;
+        interpreter.set_key(0x7, true);
+
+        for _ in 0..frames {
+            interpreter.run_frame(3).unwrap();
+        }
+
+        interpreter.general_registers[1]
+    }
+
+    #[test]
+    fn test_skip_on_key_level_vs_edge() {
+        // Level: every frame sees the held key and skips the add
+        assert_eq!(count_unskipped_with_key_held(KeyTriggerQuirk::Level, 5), 0);
+
+        // Edge: only the first frame sees the press, the other four run the add
+        assert_eq!(count_unskipped_with_key_held(KeyTriggerQuirk::Edge, 5), 4);
+    }
+
+    #[test]
+    fn test_edge_trigger_needs_release_before_next_press() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { key_trigger_quirk: KeyTriggerQuirk::Edge, ..Chip8Config::default() });
+
+        // 0x200: skip if key V0 not pressed, jump to 0x200
+        interpreter.load_rom_bytes(&[0xE0, 0xA1, 0x12, 0x00]).unwrap();
+
+        interpreter.set_keypad_state(0b1);
+        interpreter.run_frame(1).unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+
+        // Still held, no longer a new press, so ExA1 skips
+        interpreter.register_pc = 0x200;
+        interpreter.set_keypad_state(0b1);
+        interpreter.run_frame(1).unwrap();
+        assert_eq!(interpreter.register_pc, 0x204);
+
+        interpreter.register_pc = 0x200;
+        interpreter.set_keypad_state(0b0);
+        interpreter.set_keypad_state(0b1);
+        interpreter.run_frame(1).unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+    }
+
+    #[test]
+    fn test_keypad_state_mask() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.handle_event(CalicoEvent::KeyDown, CalicoKey::X);
+        interpreter.handle_event(CalicoEvent::KeyDown, CalicoKey::V);
+        assert_eq!(interpreter.keypad_state(), 0b1000_0000_0000_0001);
+
+        interpreter.set_keypad_state(0b0000_0000_0010_0100);
+        assert!(interpreter.keypad_status[2]);
+        assert!(interpreter.keypad_status[5]);
+        assert!(!interpreter.keypad_status[0]);
+        assert!(!interpreter.keypad_status[15]);
+    }
+
+    #[test]
+    fn test_seeded_rng_is_deterministic() {
+        let mut first = Chip8Interpreter::new(Chip8Config::default());
+        let mut second = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: V0 = rand & 0xFF, 0x202: jump to 0x200
+        for interpreter in [&mut first, &mut second] {
+            let program = [0xC0, 0xFF, 0x12, 0x00];
+            interpreter.load_rom_bytes(&program).unwrap();
+            interpreter.seed_rng(1234);
+        }
+
+        for _ in 0..16 {
+            first.run_frame(2).unwrap();
+            second.run_frame(2).unwrap();
+
+            assert_eq!(first.general_registers[0], second.general_registers[0]);
+        }
+    }
+
+    #[test]
+    fn test_config_rng_seed() {
+        let random_bytes = |rng_seed: u64| {
+            let mut interpreter = Chip8Interpreter::new(Chip8Config { rng_seed: Some(rng_seed), ..Chip8Config::default() });
+
+            // 0x200: V0 = rand & 0xFF, 0x202: jump to 0x200
+            interpreter.load_rom_bytes(&[0xC0, 0xFF, 0x12, 0x00]).unwrap();
+
+            (0..16).map(|_| {
+                interpreter.run_frame(2).unwrap();
+                interpreter.general_registers[0]
+            }).collect::<Vec<_>>()
+        };
+
+        assert_eq!(random_bytes(42), random_bytes(42));
+        assert_ne!(random_bytes(42), random_bytes(43));
+    }
+
+    fn run_bcd(value: u8, address: u16) -> Result<Chip8Interpreter, InterpreterError> {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x200: BCD of V0 at I
+        interpreter.load_rom_bytes(&[0xF0, 0x33]).unwrap();
+        interpreter.general_registers[0] = value;
+        interpreter.register_i = address;
+
+        interpreter.execute_next_instruction()?;
+
+        Ok(interpreter)
+    }
+
+    #[test]
+    fn test_bcd() {
+        for (value, digits) in [(0, [0, 0, 0]), (9, [0, 0, 9]), (128, [1, 2, 8]), (255, [2, 5, 5])] {
+            let interpreter = run_bcd(value, 0x300).unwrap();
+
+            assert_eq!(interpreter.memory_slice(0x300, 3), &digits);
+        }
+
+        // Last address that still fits all three digits
+        let interpreter = run_bcd(123, 4093).unwrap();
+        assert_eq!(interpreter.memory_slice(4093, 3), &[1, 2, 3]);
+    }
+
+    // Runs a single I relative opcode with I = 'memory_size' - 1 after 'setup', so the access crosses the end of memory
+    fn run_at_end_of_memory(memory_size: usize, opcode: u16, setup: impl FnOnce(&mut Chip8Interpreter)) -> Chip8Interpreter {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { memory_size, ..Chip8Config::default() });
+
+        interpreter.load_rom_bytes(&opcode.to_be_bytes()).unwrap();
+        interpreter.register_i = (memory_size - 1) as u16;
+        setup(&mut interpreter);
+
+        interpreter.execute_next_instruction().unwrap();
+
+        interpreter
+    }
+
+    #[test]
+    fn test_i_relative_access_wraps_at_end_of_memory() {
+        for memory_size in [DEFAULT_MEMORY_SIZE, XO_CHIP_MEMORY_SIZE] {
+            let last = memory_size - 1;
+
+            // Fx33
+            let interpreter = run_at_end_of_memory(memory_size, 0xF033, |interpreter| interpreter.general_registers[0] = 123);
+            assert_eq!([interpreter.memory[last], interpreter.memory[0], interpreter.memory[1]], [1, 2, 3]);
+
+            // Fx55
+            let interpreter = run_at_end_of_memory(memory_size, 0xF155, |interpreter| {
+                interpreter.general_registers[..2].copy_from_slice(&[0xAA, 0xBB]);
+            });
+            assert_eq!([interpreter.memory[last], interpreter.memory[0]], [0xAA, 0xBB]);
+
+            // Fx65
+            let interpreter = run_at_end_of_memory(memory_size, 0xF165, |interpreter| {
+                interpreter.memory[last] = 0x12;
+                interpreter.memory[0] = 0x34;
+            });
+            assert_eq!(interpreter.general_registers[..2], [0x12, 0x34]);
+
+            // Dxyn, the second row comes from 0x000
+            let interpreter = run_at_end_of_memory(memory_size, 0xD012, |interpreter| {
+                interpreter.memory[last] = 0x80;
+                interpreter.memory[0] = 0x80;
+            });
+            assert!(interpreter.frame_buffer.get_pixel(0, 0));
+            assert!(interpreter.frame_buffer.get_pixel(0, 1));
+        }
+    }
+
+    // Runs a single 8xyN opcode with the given Vx/Vy values and returns (Vx, VF)
+    fn run_alu(opcode: u16, reg_x: u8, reg_y: u8) -> (u8, u8) {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.load_rom_bytes(&opcode.to_be_bytes()).unwrap();
+
+        interpreter.current_opcode = opcode;
+        interpreter.general_registers[interpreter.get_x_from_opcode()] = reg_x;
+        interpreter.general_registers[interpreter.get_y_from_opcode()] = reg_y;
+
+        interpreter.execute_next_instruction().unwrap();
+
+        (interpreter.general_registers[interpreter.get_x_from_opcode()], interpreter.general_registers[0xF])
+    }
+
+    #[test]
+    fn test_add() {
+        assert_eq!(run_alu(0x8014, 10, 3), (13, 0));
+        assert_eq!(run_alu(0x8014, 255, 1), (0, 1));
+        assert_eq!(run_alu(0x8014, 200, 100), (44, 1));
+    }
+
+    #[test]
+    fn test_add_into_vf_keeps_flag() {
+        assert_eq!(run_alu(0x8F14, 10, 3), (0, 0));
+        assert_eq!(run_alu(0x8F14, 200, 100), (1, 1));
+    }
+
+    #[test]
+    fn test_shift_into_vf_keeps_flag() {
+        assert_eq!(run_alu(0x8F16, 0b0000_0011, 0), (1, 1));
+        assert_eq!(run_alu(0x8F16, 0b0000_0010, 0), (0, 0));
+        assert_eq!(run_alu(0x8F1E, 0b1000_0000, 0), (1, 1));
+        assert_eq!(run_alu(0x8F1E, 0b0100_0000, 0), (0, 0));
+    }
+
+    #[test]
+    fn test_shift_vy_into_vf_keeps_flag() {
+        // The flag comes from the original V1 and is written after the shifted value
+        for (opcode, reg_y, flag) in [(0x8F16, 0b0000_0100, 0), (0x8F16, 0b0000_0101, 1),
+                                      (0x8F1E, 0b1000_0001, 1), (0x8F1E, 0b0100_0001, 0)] {
+            let mut interpreter = Chip8Interpreter::new(Chip8Config { shift_quirk: ShiftQuirk::ShiftVy, ..Chip8Config::default() });
+
+            interpreter.load_rom_bytes(&u16::to_be_bytes(opcode)).unwrap();
+            interpreter.general_registers[1] = reg_y;
+            interpreter.execute_next_instruction().unwrap();
+
+            assert_eq!(interpreter.general_registers[0xF], flag, "opcode {:04x}, V1 = {:08b}", opcode, reg_y);
+            assert_eq!(interpreter.general_registers[1], reg_y);
+        }
+    }
+
+    #[test]
+    fn test_warn_vf() {
+        let run_program = |program: &[u16]| {
+            let mut interpreter = Chip8Interpreter::new(Chip8Config { warn_vf: true, ..Chip8Config::default() });
+            let bytes: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+
+            interpreter.load_rom_bytes(&bytes).unwrap();
+
+            for _ in program {
+                interpreter.execute_next_instruction().unwrap();
+            }
+
+            interpreter.vf_warning_count()
+        };
+
+        assert_eq!(run_program(&[0x8F14]), 1);
+        assert_eq!(run_program(&[0x83F4]), 1);
+        assert_eq!(run_program(&[0x8314, 0x8324]), 0);
+
+        // Off by default
+        assert_eq!(load_program(&[0x8F14]).vf_warning_count(), 0);
+    }
+
+    #[test]
+    fn test_sub() {
+        assert_eq!(run_alu(0x8015, 10, 3), (7, 1));
+        assert_eq!(run_alu(0x8015, 5, 5), (0, 1));
+        assert_eq!(run_alu(0x8015, 3, 10), (249, 0));
+        assert_eq!(run_alu(0x8015, 0, 255), (1, 0));
+    }
+
+    #[test]
+    fn test_subn() {
+        assert_eq!(run_alu(0x8017, 3, 10), (7, 1));
+        assert_eq!(run_alu(0x8017, 5, 5), (0, 1));
+        assert_eq!(run_alu(0x8017, 10, 3), (249, 0));
+        assert_eq!(run_alu(0x8017, 255, 0), (1, 0));
+    }
+
+    #[test]
+    fn test_sub_into_vf_keeps_flag() {
+        // VF - V1 with VF as destination, the flag overwrites the difference
+        assert_eq!(run_alu(0x8F15, 10, 3), (1, 1));
+        assert_eq!(run_alu(0x8F15, 3, 10), (0, 0));
+        assert_eq!(run_alu(0x8F17, 3, 10), (1, 1));
+        assert_eq!(run_alu(0x8F17, 10, 3), (0, 0));
+    }
+
+    #[test]
+    fn test_load_rom_bytes_gzip() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // gzip of 60 05 F0 18 00 E0 12 06
+        let compressed = [
+            0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4B, 0x60, 0xFD, 0x20, 0xC1, 0xF0,
+            0x40, 0x88, 0x0D, 0x00, 0x69, 0x90, 0x86, 0x9A, 0x08, 0x00, 0x00, 0x00,
+        ];
+
+        interpreter.load_rom_bytes(&compressed).unwrap();
+
+        assert_eq!(interpreter.memory_slice(0x200, 8), &[0x60, 0x05, 0xF0, 0x18, 0x00, 0xE0, 0x12, 0x06]);
+    }
+
+    #[test]
+    fn test_load_rom_bytes_uncompressed() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        assert_eq!(interpreter.memory_slice(0x200, 2), &[0x12, 0x00]);
+
+        assert!(matches!(interpreter.load_rom_bytes(&[0; 4096 - 0x200 + 1]), Err(RomTooLarge { size: 3585, max_size: 3584 })));
+        assert!(matches!(interpreter.load_rom_bytes(&[0x1F, 0x8B, 0x08, 0x00]), Err(InvalidGzip { .. })));
+        assert!(matches!(interpreter.load_rom("does/not/exist.ch8"), Err(RomUnreadable { .. })));
+    }
+
+    #[test]
+    fn test_load_address() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { load_address: 0x600, ..Chip8Config::default() });
+
+        interpreter.load_rom_bytes(&[0x60, 0x05, 0x16, 0x02]).unwrap();
+
+        assert_eq!(interpreter.pc(), 0x600);
+        assert_eq!(interpreter.memory_slice(0x600, 4), &[0x60, 0x05, 0x16, 0x02]);
+        assert_eq!(interpreter.memory_slice(0x200, 4), &[0; 4]);
+        assert_eq!(interpreter.rom(), &[0x60, 0x05, 0x16, 0x02]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.general_registers[0], 0x05);
+
+        // Less room left for the ROM
+        assert!(matches!(interpreter.load_rom_bytes(&[0; 4096 - 0x600 + 1]), Err(RomTooLarge { max_size: 2560, .. })));
+
+        for address in [0x000, 0x100, 0x1FE, 0x601, 0x1000] {
+            let mut interpreter = Chip8Interpreter::new(Chip8Config { load_address: address, ..Chip8Config::default() });
+
+            assert!(matches!(interpreter.load_rom_bytes(&[0x12, 0x00]), Err(InvalidLoadAddress { .. })));
+        }
+    }
+
+    #[test]
+    fn test_rom_offset() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { rom_offset: 3, ..Chip8Config::default() });
+
+        // 3 byte header followed by V0 = 5, jump to 0x202
+        interpreter.load_rom_bytes(&[b'C', b'8', 0x01, 0x60, 0x05, 0x12, 0x02]).unwrap();
+
+        assert_eq!(interpreter.rom(), &[0x60, 0x05, 0x12, 0x02]);
+        assert_eq!(interpreter.memory_slice(0x200, 4), &[0x60, 0x05, 0x12, 0x02]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.general_registers[0], 0x05);
+
+        // The header doesn't count towards the size limit
+        assert!(interpreter.load_rom_bytes(&[0; 4096 - 0x200 + 3]).is_ok());
+        assert!(matches!(interpreter.load_rom_bytes(&[0; 3]), Err(RomOffsetTooLarge { offset: 3, size: 3 })));
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(StackUnderflow { pc: 0x202 }.to_string(), "Stack underflow at PC=0x202");
+        assert_eq!(StackOverflow { pc: 0x2a0 }.to_string(), "Stack overflow at PC=0x2a0");
+        assert_eq!(InvalidOpcode { pc: 0x200, opcode: 0xFFFF }.to_string(), "Invalid opcode=0xffff at PC=0x200");
+        assert_eq!(PcOutOfBounds { pc: 0x1000 }.to_string(), "PC=0x1000 is outside of memory");
+        assert_eq!(NoRomLoaded.to_string(), "No ROM loaded");
+        assert_eq!(InvalidLoadAddress { address: 0x601 }.to_string(), "Load address=0x601 has to be even, at least 0x200 and inside of memory");
+        assert_eq!(RomTooLarge { size: 4000, max_size: 3584 }.to_string(), "ROM is 4000 bytes, only 3584 fit in memory");
+        assert_eq!(RomOffsetTooLarge { offset: 16, size: 10 }.to_string(), "ROM offset=16 has to be smaller than the ROM size=10");
+        assert_eq!(RomUnreadable { reason: "Permission denied".to_owned() }.to_string(),
+                   "Unable to read ROM: Permission denied");
+        assert_eq!(InvalidGzip { reason: "Truncated gzip data".to_owned() }.to_string(),
+                   "Invalid gzip ROM: Truncated gzip data");
+    }
+
+    #[test]
+    fn test_validate_rom() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 0x204 = 8xy8 and 0x208 = Fx99 don't exist
+        interpreter.load_rom_bytes(&[0x60, 0x05, 0x81, 0x24, 0x81, 0x28, 0xE1, 0x9E, 0xF1, 0x99, 0x12, 0x00]).unwrap();
+
+        assert_eq!(interpreter.validate_rom(), vec![(0x204, 0x8128), (0x208, 0xF199)]);
+    }
+
+    #[test]
+    fn test_invalid_opcode_strict_and_lenient() {
+        // 0x200: invalid 8xy8, 0x202: V0 = 0x42
+        let program = [0x81, 0x28, 0x60, 0x42];
+
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        assert!(matches!(interpreter.execute_next_instruction(), Err(InvalidOpcode { pc: 0x200, opcode: 0x8128 })));
+
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { lenient: true, ..Chip8Config::default() });
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.general_registers[0], 0x42);
+    }
+
+    #[test]
+    fn test_sys_ignored_by_default() {
+        // 0x200: SYS 0x300
+        let program = [0x03, 0x00];
+
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert!(interpreter.stack.is_empty());
+    }
+
+    #[test]
+    fn test_sys_legacy_call() {
+        // 0x200: SYS 0x300
+        let program = [0x03, 0x00];
+
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { legacy_sys: true, ..Chip8Config::default() });
+        interpreter.load_rom_bytes(&program).unwrap();
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x300);
+        assert_eq!(interpreter.stack, vec![0x202]);
+    }
+
+    #[test]
+    fn test_protect_interpreter_mem() {
+        // 0x200: F355 (store V0-V3 at I)
+        let program = [0xF3, 0x55];
+
+        let run_store = |protect_interpreter_mem: bool| {
+            let mut interpreter = Chip8Interpreter::new(Chip8Config { protect_interpreter_mem, ..Chip8Config::default() });
+            interpreter.load_rom_bytes(&program).unwrap();
+            interpreter.register_i = FONT_START_ADDRESS;
+            interpreter.execute_next_instruction().unwrap();
+
+            interpreter.memory_slice(FONT_START_ADDRESS, 4).to_vec()
+        };
+
+        assert_eq!(run_store(true), C8_FONT_SET[..4]);
+        assert_eq!(run_store(false), [0, 0, 0, 0]);
+
+        // Writes from 0x200 on still go through
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { protect_interpreter_mem: true, ..Chip8Config::default() });
+        interpreter.load_rom_bytes(&program).unwrap();
+        interpreter.register_i = 0x1FE;
+        interpreter.general_registers[2] = 0xAB;
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.memory_slice(0x1FE, 3), [0x00, 0x00, 0xAB]);
+    }
+
+    fn draw_font_zero_at_right_edge(clip_quirk: ClipQuirk) -> Chip8Interpreter {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.set_clip_quirk(clip_quirk);
+        interpreter.general_registers[0] = 62;
+        interpreter.general_registers[1] = 0;
+        interpreter.register_i = 0x050;
+
+        // Top row of font '0' is 0xF0, four pixels wide
+        interpreter.draw(0, 1, 1);
+
+        interpreter
+    }
+
+    #[test]
+    fn test_draw_wraps_at_right_edge() {
+        let interpreter = draw_font_zero_at_right_edge(ClipQuirk::Wrap);
+
+        assert!(interpreter.frame_buffer.get_pixel(62, 0));
+        assert!(interpreter.frame_buffer.get_pixel(63, 0));
+        assert!(interpreter.frame_buffer.get_pixel(0, 0));
+        assert!(interpreter.frame_buffer.get_pixel(1, 0));
+    }
+
+    #[test]
+    fn test_draw_clips_at_right_edge() {
+        let interpreter = draw_font_zero_at_right_edge(ClipQuirk::Clip);
+
+        assert!(interpreter.frame_buffer.get_pixel(62, 0));
+        assert!(interpreter.frame_buffer.get_pixel(63, 0));
+        assert!(!interpreter.frame_buffer.get_pixel(0, 0));
+        assert!(!interpreter.frame_buffer.get_pixel(1, 0));
+    }
+
+    #[test]
+    fn test_vip_draw_wraps_right_and_clips_bottom() {
+        // Takes priority over ClipQuirk
+        let mut interpreter = draw_font_zero_at_right_edge(ClipQuirk::Clip);
+
+        interpreter.frame_buffer.clear();
+        interpreter.set_vip_draw_quirk(VipDrawQuirk::WrapRightClipBottom);
+        interpreter.draw(0, 1, 1);
+
+        assert!(interpreter.frame_buffer.get_pixel(63, 0));
+        assert!(interpreter.frame_buffer.get_pixel(0, 0));
+        assert!(interpreter.frame_buffer.get_pixel(1, 0));
+
+        // Font '0' is 5 rows tall, only the top 2 fit at y = 30
+        interpreter.frame_buffer.clear();
+        interpreter.general_registers[0] = 10;
+        interpreter.general_registers[1] = 30;
+        interpreter.draw(0, 1, 5);
+
+        assert!(interpreter.frame_buffer.get_pixel(10, 30));
+        assert!(interpreter.frame_buffer.get_pixel(10, 31));
+        assert!((0..3).all(|y| !interpreter.frame_buffer.get_pixel(10, y)));
+    }
+
+    #[test]
+    fn test_draw_start_position_wraps_when_clipping() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.set_clip_quirk(ClipQuirk::Clip);
+        interpreter.general_registers[0] = 64 + 2;
+        interpreter.general_registers[1] = 32 + 3;
+        interpreter.register_i = 0x050;
+
+        interpreter.draw(0, 1, 1);
+
+        assert!(interpreter.frame_buffer.get_pixel(2, 3));
+    }
+
+    #[test]
+    fn test_font_character_points_into_font_set() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // F029
+        interpreter.load_rom_bytes(&[0xF0, 0x29]).unwrap();
+        interpreter.general_registers[0] = 0xA;
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.register_i, FONT_START_ADDRESS + 0xA * 5);
+        assert_eq!(interpreter.memory_slice(interpreter.register_i, 5), &C8_FONT_SET[50..55]);
+    }
+
+    #[test]
+    fn test_big_font_character_points_into_big_font_set() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // F130, F129
+        interpreter.load_rom_bytes(&[0xF1, 0x30, 0xF1, 0x29]).unwrap();
+        interpreter.general_registers[1] = 0x7;
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.register_i, 0x0A0 + 0x7 * 10);
+        assert_eq!(interpreter.memory_slice(interpreter.register_i, 10), &SCHIP_BIG_FONT_SET[70..80]);
+
+        // The small font is still where it was
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.register_i, FONT_START_ADDRESS + 0x7 * 5);
+        assert_eq!(interpreter.memory_slice(interpreter.register_i, 5), &C8_FONT_SET[35..40]);
+    }
+
+    #[test]
+    fn test_rpl_flags_store_and_load() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // F375, clobber V0-V3 with 6000-6300, F385
+        interpreter.load_rom_bytes(&[0xF3, 0x75, 0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0x63, 0x00, 0xF3, 0x85]).unwrap();
+        interpreter.general_registers[..5].copy_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55]);
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(&interpreter.rpl_flags()[..5], &[0x11, 0x22, 0x33, 0x44, 0x00]);
+
+        for _ in 0..4 {
+            interpreter.execute_next_instruction().unwrap();
+        }
+
+        assert_eq!(&interpreter.general_registers[..4], &[0x00; 4]);
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(&interpreter.general_registers[..5], &[0x11, 0x22, 0x33, 0x44, 0x55]);
+    }
+
+    #[test]
+    fn test_load_test_pattern() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.load_test_pattern();
+
+        assert!(interpreter.take_draw_flag());
+        assert!(interpreter.frame_buffer.get_pixel(0, 0));
+        assert!(interpreter.frame_buffer.get_pixel(63, 31));
+
+        // Jumping to itself leaves the screen alone
+        interpreter.run_frame(10).unwrap();
+
+        assert_eq!(interpreter.pc(), 0x200);
+        assert!(interpreter.frame_buffer.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn test_scroll_opcodes() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 00D2, 00C5
+        interpreter.load_rom_bytes(&[0x00, 0xD2, 0x00, 0xC5]).unwrap();
+        interpreter.frame_buffer.flip_pixel(4, 10);
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert!(interpreter.frame_buffer.get_pixel(4, 8));
+        assert!(interpreter.take_draw_flag());
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert!(interpreter.frame_buffer.get_pixel(4, 13));
+        assert!(!interpreter.frame_buffer.get_pixel(4, 8));
+        assert!(interpreter.stack_slice().is_empty());
+    }
+
+    // Lights (4, 10) in both planes, selects only plane 2 with F201 and runs 'opcode' on it
+    fn scroll_plane_two(opcode: u16, hires: bool) -> Chip8Interpreter {
+        let mut interpreter = load_program(&[0xF201, opcode]);
+
+        interpreter.frame_buffer.set_hires(hires);
+        interpreter.frame_buffer.flip_plane_pixel(0, 4, 10);
+        interpreter.frame_buffer.flip_plane_pixel(1, 4, 10);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.frame_buffer.selected_planes(), 0b10);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert!(interpreter.take_draw_flag());
+
+        // Plane 1 isn't selected, so it stays put
+        assert!(interpreter.frame_buffer.get_plane_pixel(0, 4, 10));
+        assert!(!interpreter.frame_buffer.get_plane_pixel(1, 4, 10));
+
+        interpreter
+    }
+
+    #[test]
+    fn test_scroll_opcodes_only_touch_selected_planes() {
+        // 00C3
+        let interpreter = scroll_plane_two(0x00C3, false);
+        assert!(interpreter.frame_buffer.get_plane_pixel(1, 4, 13));
+        assert_eq!(interpreter.frame_buffer.pixel_value(4, 13), 2);
+        assert_eq!(interpreter.frame_buffer.pixel_value(4, 10), 1);
+
+        // 00C3 in hires
+        let interpreter = scroll_plane_two(0x00C3, true);
+        assert_eq!(interpreter.frame_buffer.resolution(), (128, 64));
+        assert!(interpreter.frame_buffer.get_plane_pixel(1, 4, 13));
+
+        // 00FB, 4 pixels right
+        let interpreter = scroll_plane_two(0x00FB, false);
+        assert!(interpreter.frame_buffer.get_plane_pixel(1, 8, 10));
+
+        // 00FC, 4 pixels left
+        let interpreter = scroll_plane_two(0x00FC, false);
+        assert!(interpreter.frame_buffer.get_plane_pixel(1, 0, 10));
+    }
+
+    #[test]
+    fn test_draw_to_both_planes() {
+        // Select planes 1 and 2, draw a 1 row sprite at (0, 0), plane 1 data at I and plane 2 data at I + 1
+        let mut interpreter = load_program(&[0xF301, 0xD001]);
+
+        interpreter.register_i = 0x300;
+        interpreter.memory[0x300] = 0b1100_0000;
+        interpreter.memory[0x301] = 0b1010_0000;
+
+        interpreter.execute_next_instruction().unwrap();
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!((0..3).map(|x| interpreter.frame_buffer.pixel_value(x, 0)).collect::<Vec<_>>(), vec![3, 1, 2]);
+        assert_eq!(interpreter.general_registers[0xF], 0);
+
+        // 00E0 only clears the selected planes
+        interpreter.frame_buffer.select_planes(0b10);
+        interpreter.frame_buffer.clear();
+
+        assert_eq!(interpreter.frame_buffer.pixel_value(0, 0), 1);
+        assert_eq!(interpreter.frame_buffer.pixel_value(2, 0), 0);
+    }
+
+    #[test]
+    fn test_hires_toggle() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 00FF, 00FF, 00FE
+        interpreter.load_rom_bytes(&[0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFE]).unwrap();
+
+        assert_eq!(interpreter.frame_buffer.resolution(), (64, 32));
+        assert!(!interpreter.take_resolution_changed());
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.frame_buffer.resolution(), (128, 64));
+        assert!(interpreter.take_resolution_changed());
+        assert!(!interpreter.take_resolution_changed());
+
+        // Already in hires
+        interpreter.execute_next_instruction().unwrap();
+
+        assert!(!interpreter.take_resolution_changed());
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.frame_buffer.resolution(), (64, 32));
+        assert!(interpreter.take_resolution_changed());
+    }
+
+    #[test]
+    fn test_draw_in_hires() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 00FF, D011
+        interpreter.load_rom_bytes(&[0x00, 0xFF, 0xD0, 0x11]).unwrap();
+        interpreter.general_registers[0] = 100;
+        interpreter.general_registers[1] = 50;
+        interpreter.register_i = 0x050;
+
+        interpreter.execute_next_instruction().unwrap();
+        interpreter.execute_next_instruction().unwrap();
+
+        // Top row of font '0' is 0xF0
+        assert!(interpreter.frame_buffer.get_pixel(100, 50));
+        assert!(interpreter.frame_buffer.get_pixel(103, 50));
+        assert!(!interpreter.frame_buffer.get_pixel(104, 50));
+    }
+
+    #[test]
+    fn test_take_draw_flag() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // D011 with font '0'
+        interpreter.load_rom_bytes(&[0xD0, 0x11]).unwrap();
+        interpreter.register_i = 0x050;
+
+        assert!(!interpreter.take_draw_flag());
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert!(interpreter.take_draw_flag());
+        assert!(!interpreter.take_draw_flag());
+    }
+
+    #[test]
+    fn test_unchanged_screen_leaves_draw_flag() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // D011 with an all zero sprite, 00E0 on an empty screen
+        interpreter.load_rom_bytes(&[0xD0, 0x11, 0x00, 0xE0, 0x00, 0x00]).unwrap();
+        interpreter.register_i = 0x204;
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert!(!interpreter.take_draw_flag());
+        assert_eq!(interpreter.general_registers[0xF], 0);
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert!(!interpreter.take_draw_flag());
+    }
+
+    #[test]
+    fn test_large_rom_needs_xo_chip_memory() {
+        let rom = vec![0; 8000];
+
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+        assert!(interpreter.load_rom_bytes(&rom).is_err());
+
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { memory_size: XO_CHIP_MEMORY_SIZE, ..Chip8Config::default() });
+        assert!(interpreter.load_rom_bytes(&rom).is_ok());
+        assert_eq!(interpreter.rom().len(), 8000);
+        assert_eq!(interpreter.memory_slice(0xFFF0, 0x20).len(), 0x10);
+    }
+
+    #[test]
+    fn test_load_registers_wraps_at_end_of_memory() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // F165
+        interpreter.load_rom_bytes(&[0xF1, 0x65]).unwrap();
+        interpreter.register_i = 4095;
+        interpreter.memory[4095] = 0x12;
+        interpreter.memory[0] = 0x34;
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.general_registers[0], 0x12);
+        assert_eq!(interpreter.general_registers[1], 0x34);
+    }
+
+    #[test]
+    fn test_draw_sprite_wraps_at_end_of_memory() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // D01F
+        interpreter.load_rom_bytes(&[0xD0, 0x1F]).unwrap();
+        interpreter.register_i = 4090;
+        interpreter.memory[4095] = 0x80;
+        interpreter.memory[0] = 0x80;
+
+        interpreter.execute_next_instruction().unwrap();
+
+        // Row 5 comes from 4095, row 6 from 0x000
+        assert!(interpreter.frame_buffer.get_pixel(0, 5));
+        assert!(interpreter.frame_buffer.get_pixel(0, 6));
+        assert!(!interpreter.frame_buffer.get_pixel(1, 6));
+    }
+
+    #[test]
+    fn test_long_load_i() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { memory_size: XO_CHIP_MEMORY_SIZE, ..Chip8Config::default() });
+
+        // F000 ABCD, 6001
+        interpreter.load_rom_bytes(&[0xF0, 0x00, 0xAB, 0xCD, 0x60, 0x01]).unwrap();
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.register_i, 0xABCD);
+        assert_eq!(interpreter.register_pc, 0x204);
+
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.general_registers[0], 0x01);
+        assert!(interpreter.validate_rom().is_empty());
+    }
+
+    #[test]
+    fn test_skip_over_long_load_i() {
+        // 3000 skips the whole F000 0300 and lands on 6A01
+        let mut interpreter = load_program(&[0x3000, 0xF000, 0x0300, 0x6A01]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.pc(), 0x206);
+
+        assert!(matches!(interpreter.execute_next_instruction(), Ok(0x6A01)));
+        assert_eq!(interpreter.general_registers[0xA], 0x01);
+        assert_eq!(interpreter.register_i, 0);
+
+        // Without the skip the long load runs as usual
+        let mut interpreter = load_program(&[0x4000, 0xF000, 0x0300, 0x6A01]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert!(matches!(interpreter.execute_next_instruction(), Ok(0xF000)));
+        assert_eq!(interpreter.register_i, 0x0300);
+        assert_eq!(interpreter.pc(), 0x206);
+
+        // Key skips step over it too
+        let mut interpreter = load_program(&[0xE0A1, 0xF000, 0x0300, 0x6A01]);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.pc(), 0x206);
+    }
+
+    #[test]
+    fn test_export_memory_after_store() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // A300, F255
+        interpreter.load_rom_bytes(&[0xA3, 0x00, 0xF2, 0x55]).unwrap();
+        interpreter.general_registers[0..3].copy_from_slice(&[0x11, 0x22, 0x33]);
+
+        interpreter.execute_next_instruction().unwrap();
+        interpreter.execute_next_instruction().unwrap();
+
+        let memory = interpreter.export_memory();
+
+        assert_eq!(memory.len(), DEFAULT_MEMORY_SIZE);
+        assert_eq!(&memory[0x300..0x304], &[0x11, 0x22, 0x33, 0x00]);
+        assert_eq!(&memory[0x200..0x204], &[0xA3, 0x00, 0xF2, 0x55]);
+    }
+
+    #[test]
+    fn test_calico_key_hex_conversion() {
+        assert_eq!(CalicoKey::Mk1.to_hex(), Some(0x1));
+        assert_eq!(CalicoKey::Mk4.to_hex(), Some(0xC));
+        assert_eq!(CalicoKey::Q.to_hex(), Some(0x4));
+        assert_eq!(CalicoKey::W.to_hex(), Some(0x5));
+        assert_eq!(CalicoKey::R.to_hex(), Some(0xD));
+        assert_eq!(CalicoKey::A.to_hex(), Some(0x7));
+        assert_eq!(CalicoKey::Z.to_hex(), Some(0xA));
+        assert_eq!(CalicoKey::X.to_hex(), Some(0x0));
+        assert_eq!(CalicoKey::C.to_hex(), Some(0xB));
+        assert_eq!(CalicoKey::V.to_hex(), Some(0xF));
+        assert_eq!(CalicoKey::Other.to_hex(), None);
+
+        for hex in 0..16 {
+            assert_eq!(CalicoKey::from_hex(hex).to_hex(), Some(hex));
+        }
+
+        assert_eq!(CalicoKey::from_hex(0x10), CalicoKey::Other);
+    }
+
+    #[test]
+    fn test_handle_event_uses_standard_layout() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.handle_event(CalicoEvent::KeyDown, CalicoKey::W);
+        interpreter.handle_event(CalicoEvent::KeyDown, CalicoKey::V);
+
+        assert!(interpreter.key_state(0x5));
+        assert!(interpreter.key_state(0xF));
+        assert_eq!(interpreter.keypad_state(), 1 << 0x5 | 1 << 0xF);
+
+        interpreter.handle_event(CalicoEvent::KeyUp, CalicoKey::W);
+
+        assert!(!interpreter.key_state(0x5));
+    }
+
+    #[test]
+    fn test_wait_for_key_sees_tap_within_frame() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // F30A
+        interpreter.load_rom_bytes(&[0xF3, 0x0A]).unwrap();
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x200);
+
+        // Pressed and released before the interpreter got to run
+        interpreter.set_key(0x7, true);
+        interpreter.set_key(0x7, false);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert_eq!(interpreter.general_registers[3], 0x7);
+    }
+
+    #[test]
+    fn test_wait_for_key_latch_is_consumed() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // F00A, F10A
+        interpreter.load_rom_bytes(&[0xF0, 0x0A, 0xF1, 0x0A]).unwrap();
+
+        interpreter.set_keypad_state(1 << 0x0);
+        interpreter.set_keypad_state(0);
+
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert_eq!(interpreter.general_registers[0], 0x0);
+
+        // The tap was used up by the first wait
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+    }
+
+    #[test]
+    fn test_key_press_latch_cleared_after_frame() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // 1200 (spin), the latch from a tap during this frame shouldn't leak into later ones
+        interpreter.load_rom_bytes(&[0x12, 0x00]).unwrap();
+
+        interpreter.set_key(0x2, true);
+        interpreter.set_key(0x2, false);
+        interpreter.run_frame(1).unwrap();
+
+        assert_eq!(interpreter.key_press_latch, 0);
+    }
+
+    #[test]
+    fn test_wait_for_key_on_press() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // F20A
+        interpreter.load_rom_bytes(&[0xF2, 0x0A]).unwrap();
+
+        interpreter.set_key(0xB, true);
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert_eq!(interpreter.general_registers[2], 0xB);
+    }
+
+    #[test]
+    fn test_wait_for_key_lowest_of_held_keys() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // F20A
+        interpreter.load_rom_bytes(&[0xF2, 0x0A]).unwrap();
+
+        interpreter.set_key(0xE, true);
+        interpreter.set_key(0x3, true);
+        interpreter.set_key(0x7, true);
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert_eq!(interpreter.general_registers[2], 0x3);
+    }
+
+    #[test]
+    fn test_wait_for_key_on_release_with_two_keys_held() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { wait_key_quirk: WaitKeyQuirk::OnRelease, ..Chip8Config::default() });
+
+        // F20A, then skip if V2 isn't pressed
+        interpreter.load_rom_bytes(&[0xF2, 0x0A, 0xE2, 0xA1]).unwrap();
+
+        interpreter.set_key(0x9, true);
+        interpreter.set_key(0x4, true);
+        interpreter.execute_next_instruction().unwrap();
+
+        // Releasing the other key doesn't finish it
+        interpreter.set_key(0x9, false);
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x200);
+
+        interpreter.set_key(0x4, false);
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert_eq!(interpreter.general_registers[2], 0x4);
+
+        // The stored key is already up, so a following ExA1 skips
+        interpreter.execute_next_instruction().unwrap();
+        assert_eq!(interpreter.register_pc, 0x206);
+    }
+
+    #[test]
+    fn test_wait_for_key_on_release() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { wait_key_quirk: WaitKeyQuirk::OnRelease, ..Chip8Config::default() });
+
+        // F20A
+        interpreter.load_rom_bytes(&[0xF2, 0x0A]).unwrap();
+
+        interpreter.set_key(0xB, true);
+        interpreter.execute_next_instruction().unwrap();
+        interpreter.execute_next_instruction().unwrap();
+
+        // Still held, keeps waiting even if another key joins in
+        assert_eq!(interpreter.register_pc, 0x200);
+
+        interpreter.set_key(0xC, true);
+        interpreter.set_key(0xB, false);
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert_eq!(interpreter.general_registers[2], 0xB);
+    }
+
+    #[test]
+    fn test_wait_for_key_on_release_tap() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { wait_key_quirk: WaitKeyQuirk::OnRelease, ..Chip8Config::default() });
+
+        // F20A
+        interpreter.load_rom_bytes(&[0xF2, 0x0A]).unwrap();
+
+        interpreter.set_key(0x3, true);
+        interpreter.set_key(0x3, false);
+        interpreter.execute_next_instruction().unwrap();
+
+        assert_eq!(interpreter.register_pc, 0x202);
+        assert_eq!(interpreter.general_registers[2], 0x3);
     }
 }
@@ -0,0 +1,41 @@
+use rusty_calico_c8::gzip;
+
+// -identify, ROMs are matched by the CRC-32 of their (unpacked) bytes
+pub struct KnownRom {
+    pub crc32: u32,
+    pub name: &'static str,
+    // Name for -quirks
+    pub quirks: &'static str,
+}
+
+// Only ROMs whose exact bytes were checked belong here, a wrong hash just never matches
+const KNOWN_ROMS: &[KnownRom] = &[
+    KnownRom { crc32: 0x37A6_58A2, name: "Maze (David Winter)", quirks: "chip8" },
+];
+
+pub fn identify(rom: &[u8]) -> (u32, Option<&'static KnownRom>) {
+    let crc32 = gzip::crc32(rom);
+
+    (crc32, KNOWN_ROMS.iter().find(|known_rom| known_rom.crc32 == crc32))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_identify() {
+        let maze = [
+            0xA2, 0x1E, 0xC2, 0x01, 0x32, 0x01, 0xA2, 0x1A, 0xD0, 0x14, 0x70, 0x04, 0x30, 0x40, 0x12, 0x00, 0x60, 0x00,
+            0x71, 0x04, 0x31, 0x20, 0x12, 0x00, 0x12, 0x18, 0x80, 0x40, 0x20, 0x10, 0x20, 0x40, 0x80, 0x10,
+        ];
+
+        let (crc32, known_rom) = identify(&maze);
+
+        assert_eq!(crc32, 0x37A6_58A2);
+        assert_eq!(known_rom.map(|known_rom| known_rom.name), Some("Maze (David Winter)"));
+        assert_eq!(known_rom.map(|known_rom| known_rom.quirks), Some("chip8"));
+
+        assert!(identify(&maze[..32]).1.is_none());
+    }
+}
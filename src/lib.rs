@@ -0,0 +1,9 @@
+pub mod interpreter;
+pub mod frame_buffer;
+pub mod gzip;
+pub mod disassembler;
+
+pub use crate::frame_buffer::FrameBuffer;
+pub use crate::interpreter::{CalicoEvent, CalicoKey, Chip8Config, Chip8Interpreter, ClipQuirk, FrameOutcome,
+                             FrameResult, InterpreterError, KeyTriggerQuirk, KeypadState, MemoryQuirk, ShiftQuirk,
+                             TimingMode, VipDrawQuirk, WaitKeyQuirk};
@@ -1,48 +1,391 @@
 use std::process::exit;
 
-use crate::cmd_args::ApplicationCmdSettings;
+use rusty_calico_c8::disassembler::RomInfo;
+use rusty_calico_c8::{CalicoKey, Chip8Interpreter};
+
+use crate::cmd_args::{ApplicationCmdSettings, Backend};
+use crate::emulator_error::EmulatorError;
+#[cfg(feature = "sdl")]
 use crate::emulator::Emulator;
 
 mod cmd_args;
+mod backends;
+mod emulator_error;
+mod known_roms;
+mod quirks_report;
+mod rom_source;
+
+// Shared by the frontends, a build without any has nothing that runs a ROM
+#[cfg(any(feature = "sdl", feature = "terminal"))]
+mod input_script;
+#[cfg(any(feature = "sdl", feature = "terminal"))]
+mod profile;
+#[cfg(any(feature = "sdl", feature = "terminal"))]
+mod rpl_flags;
+#[cfg(any(feature = "sdl", feature = "terminal"))]
+mod state_dump;
+#[cfg(any(feature = "sdl", feature = "terminal"))]
+mod trace_file;
+
+// Everything below only makes sense with an SDL window
+#[cfg(feature = "sdl")]
 mod emulator;
-mod interpreter;
-mod frame_buffer;
+#[cfg(feature = "sdl")]
 mod audio;
+#[cfg(feature = "sdl")]
+mod debug_overlay;
+#[cfg(feature = "sdl")]
+mod replay;
+#[cfg(feature = "sdl")]
+mod screenshot;
+
+fn print_usage() {
+    println!("usage: rusty-calico-c8 <rom-path, '-' for stdin or 'help'> <more rom-paths> <args>");
+    println!("args explanation:");
+    println!("-window_size:x:y = sets window width to 'x' and height to 'y' (default = 640 x 320)");
+    println!("-scale:x = sets window size to 64 * 'x' by 32 * 'x', can't be used with -window_size (default = 10)");
+    println!("-integer_scale = scales the image by whole multiples only, letterboxing the rest (default = false)");
+    println!("-scanlines = leaves a dark gap between pixels when they're at least 3 screen pixels big (default = false)");
+    println!("-phosphor or -phosphor:x = fades pixels out instead of switching them off, by 'x' per frame (default = off, x = 48)");
+    println!("-dim_decay:x = unlit pixels stay dimmed until 'x' frames after going off, ignored with -phosphor (default = off)");
+    println!("-flicker_reduce = keeps pixels lit for one extra frame to hide sprite flicker, ignored with -phosphor or -dim_decay (default = false)");
+    println!("-fps_cap:x = limits rendering to 'x' frames per second, 0 = uncapped (default = 60)");
+    println!("-plane_colors:a:b:c:d = RRGGBB colors for pixel values 0-3, 0 = off, 1/2 = plane 1/2 and 3 = both (default = 000000:FFFFFF:FF0000:0000FF)");
+    println!("-clock_speed:x = sets clock speed to 'x' (default = 600)");
+    println!("-ipf:x = runs 'x' instructions per frame, can't be used with -clock_speed (default = clock_speed / 60)");
+    println!("-timing:x = 'instructions' runs a fixed number per frame, 'vip' budgets frames by COSMAC VIP machine cycles (default = instructions)");
+    println!("-no_sound = disables the beep sound (default = false)");
+    println!("-audio_latency:x = audio buffer size of 'x' samples, a power of two from 16 to 8192, lower beeps sooner but may crackle (default = picked by SDL)");
+    println!("-max_runtime:x = quits on its own after 'x' seconds, 0 = unlimited (default = 0)");
+    println!("-start_paused = starts with emulation paused, use P to resume and N to single step (default = false)");
+    println!("-clip = clips sprites at screen edges instead of wrapping them (default = false)");
+    println!("-wait_release = Fx0A waits for the key to be released instead of pressed (default = false)");
+    println!("-vip_draw = sprites wrap past the right edge but are clipped at the bottom, overrides -clip (default = false)");
+    println!("-edge_keys = Ex9E/ExA1 only see a key as pressed during the frame it went down (default = false)");
+    println!("-record:x = records keypad input to replay file 'x' (default = off)");
+    println!("-playback:x = plays back keypad input from replay file 'x' instead of the keyboard (default = off)");
+    println!("-input_script:x = presses and releases keys as listed in script file 'x', one 'frame key down|up' per line (default = off)");
+    println!("-dump_memory_on_exit:x = writes the whole memory image to file 'x' when quitting (default = off)");
+    println!("-trace:x = writes every executed instruction to file 'x', it grows quickly (default = off)");
+    println!("-rpl_flags:x = keeps the SCHIP Fx75/Fx85 flags in file 'x' between runs (default = off)");
+    println!("-dump_state_on_error:x = writes registers, memory and screen as JSON to file 'x' if the ROM fails (needs the serde feature) (default = off)");
+    println!("-keymap:x = comma separated key names for CHIP8 keys 0 through F, hotkeys can't be used (default = 1234/QWER/ASDF/ZXCV)");
+    println!("-version = used instead of the ROM path, prints the emulator version and exits");
+    println!("-keys = used instead of the ROM path, prints which keyboard keys map to the CHIP8 keypad and exits");
+    println!("-test_pattern = used instead of the ROM path, shows a border and checkerboard to check scaling and colors");
+    println!("-backend:x = draws with 'sdl' or in the 'terminal' (needs the terminal feature) (default = sdl)");
+    println!("-validate = lists opcodes in the ROM this emulator can't run, then exits (default = false)");
+    println!("-quirks_report or -quirks_report:x = runs Timendus' quirks test ROM without a window, picks menu entry 'x' and prints pass/fail per quirk (default = off, x = 1)");
+    println!("-info = prints size, platform hints and the first instructions of the ROM, then exits (default = false)");
+    println!("-identify = prints the CRC-32 of the ROM and, for known ROMs, its title and quirk profile, then exits (default = false)");
+    println!("-lenient = logs invalid opcodes and skips them instead of quitting (default = false)");
+    println!("-warn_vf = warns on stderr when an 8xyN instruction uses VF as an operand (default = false)");
+    println!("-legacy_sys = runs 0nnn as a call to machine code at nnn instead of ignoring it (default = false)");
+    println!("-protect_interpreter_mem = ignores Fx33/Fx55 writes below 0x200 so the font can't be overwritten (default = false)");
+    println!("-seed:x = seeds the random number generator with 'x' so Cxnn gives the same numbers every run (default = random)");
+    println!("-rom_offset:x = skips a header of 'x' bytes at the start of the ROM file (default = 0)");
+    println!("-load_address:x = loads the ROM and starts running at hex address 'x', e.g. 600 for ETI-660 ROMs (default = 200)");
+    println!("-ascii_dump = prints the screen as '#' and ' ' to stdout when it changes, at most twice a second (default = false)");
+    println!("-profile = counts executed instructions per opcode and prints the totals when quitting (default = false)");
+    println!("-config:x = reads default settings from config file 'x' (default = ~/.config/rusty-calico-c8/config.toml)");
+    println!("-quirks:x = selects quirk profile 'x', one of chip8, schip or xochip (default = none)");
+}
+
+// Only checked where the ROM path would be, like help
+fn is_version_request(args: &[String]) -> bool {
+    matches!(args.get(1).map(String::as_str), Some("-version" | "--version"))
+}
+
+// No ROM given, an explicit help request, or a flag where the ROM path should be
+fn is_help_request(args: &[String]) -> bool {
+    match args.get(1) {
+        Some(first) => matches!(first.as_str(), "help" | "-h" | "--help") ||
+            (first.starts_with('-') && !matches!(first.as_str(), "-keys" | "-test_pattern") && !rom_source::is_stdin(first)),
+        None => true
+    }
+}
+
+// CHIP8 keypad order, the same as the README table
+const KEYPAD_ROWS: [[u8; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+
+// 'keymap' holds the key names for hex keys 0 through F, None = built-in layout
+fn format_key_grid(keymap: Option<&[String]>) -> Vec<String> {
+    let key_name = |hex: u8| match keymap {
+        Some(keymap) => keymap[hex as usize].clone(),
+        None => CalicoKey::from_hex(hex).label().to_owned()
+    };
+
+    let width = (0..16).map(|hex| key_name(hex).len()).max().unwrap_or(1);
+
+    KEYPAD_ROWS.iter()
+        .map(|row| {
+            row.iter()
+                .map(|hex| format!("{:>width$} = {:X}", key_name(*hex), hex, width = width))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        })
+        .collect()
+}
+
+// 0 is a normal quit, 1 is kept for anything without its own code
+fn exit_code(error: &EmulatorError) -> i32 {
+    match error {
+        EmulatorError::Frontend(_) => 1,
+        EmulatorError::RomLoad(_) => 2,
+        EmulatorError::Interpreter(_) => 3,
+    }
+}
+
+// -quirks_report, any failed quirk check
+const QUIRK_CHECK_FAILED_EXIT_CODE: i32 = 4;
+
+// -info and -validate only need the ROM loaded, not a frontend
+fn load_interpreter(parsed_args: &ApplicationCmdSettings, rom_path: &str) -> Result<Chip8Interpreter, EmulatorError> {
+    let mut interpreter = Chip8Interpreter::new(parsed_args.interpreter_config());
+
+    rom_source::load(&mut interpreter, rom_path)
+        .map_err(|e| EmulatorError::RomLoad(format!("Unable to load '{}': {}", rom_path, e)))?;
+
+    Ok(interpreter)
+}
+
+#[cfg(feature = "sdl")]
+fn run_sdl(parsed_args: ApplicationCmdSettings) -> Result<(), EmulatorError> {
+    Emulator::new(parsed_args).run()
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_sdl(_parsed_args: ApplicationCmdSettings) -> Result<(), EmulatorError> {
+    Err(EmulatorError::Frontend("This build doesn't include the SDL frontend, rebuild with '--features sdl' or use -backend:terminal".to_owned()))
+}
+
+#[cfg(feature = "terminal")]
+fn run_terminal(parsed_args: &ApplicationCmdSettings) -> Result<(), EmulatorError> {
+    backends::terminal::run(parsed_args)
+}
+
+#[cfg(not(feature = "terminal"))]
+fn run_terminal(_parsed_args: &ApplicationCmdSettings) -> Result<(), EmulatorError> {
+    Err(EmulatorError::Frontend("This build doesn't include the terminal backend, rebuild with '--features terminal'".to_owned()))
+}
 
 fn main() {
     let args: Vec<_> = std::env::args().collect();
 
-    if args.len() < 2 || args[1] == "help" {
-        println!("usage: rusty-calico-c8 <rom-path or 'help> <args>");
-        println!("args explanation:");
-        println!("-window_size:x:y = sets window width to 'x' and height to 'y' (default = 640 x 320)");
-        println!("-clock_speed:x = sets clock speed to 'x' (default = 600)");
-        println!("-no_sound = disables the beep sound (default = false)");
+    if is_version_request(&args) {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
         return;
     }
 
-    let rom_path = &args[1];
+    if is_help_request(&args) {
+        print_usage();
+
+        return;
+    }
 
-    let parsed_args = if args.len() == 2 {
-        ApplicationCmdSettings::new()
-    } else {
-        match ApplicationCmdSettings::new_from_args(&args) {
-            Ok(val) => val,
+    // Takes the place of the ROM path, so any other args (-keymap) are still applied
+    if args[1] == "-keys" {
+        match ApplicationCmdSettings::load_config(&args)
+            .and_then(|config| ApplicationCmdSettings::from_config_and_args(config.as_deref(), &args)) {
+            Ok(parsed_args) => {
+                for line in format_key_grid(parsed_args.keymap.as_deref()) {
+                    println!("{}", line);
+                }
+            }
             Err(e) => {
                 println!("{}", e);
 
                 exit(-1)
             }
         }
+
+        return;
+    }
+
+    let rom_path = &args[1];
+
+    // Also takes the place of the ROM path, nothing is loaded so -info and -validate don't apply
+    let test_pattern = rom_path == "-test_pattern";
+
+    let parsed_args = match ApplicationCmdSettings::load_config(&args)
+        .and_then(|config| ApplicationCmdSettings::from_config_and_args(config.as_deref(), &args)) {
+        Ok(val) => val,
+        Err(e) => {
+            println!("{}", e);
+
+            exit(-1)
+        }
     };
 
-    match Emulator::new(parsed_args).run(rom_path) {
+    if parsed_args.dump_state_path.is_some() && !cfg!(feature = "serde") {
+        println!("-dump_state_on_error needs a build with '--features serde'");
+
+        exit(-1)
+    }
+
+    if parsed_args.info_only && !test_pattern {
+        match load_interpreter(&parsed_args, rom_path).map(|interpreter| RomInfo::new(interpreter.rom(), 10)) {
+            Ok(info) => {
+                println!("Size: {} bytes ({} words)", info.size, info.words);
+                println!("Uses SCHIP opcodes: {}", if info.uses_schip { "yes" } else { "no" });
+                println!("Uses XO-CHIP opcodes: {}", if info.uses_xochip { "yes" } else { "no" });
+                println!("First instructions:");
+
+                for (address, opcode, mnemonic) in &info.first_instructions {
+                    println!("  {:#05x}: {:04x}  {}", address, opcode, mnemonic);
+                }
+            }
+            Err(e) => {
+                println!("{}", e);
+
+                exit(exit_code(&e))
+            }
+        }
+
+        return;
+    }
+
+    if parsed_args.identify_only && !test_pattern {
+        match load_interpreter(&parsed_args, rom_path).map(|interpreter| known_roms::identify(interpreter.rom())) {
+            Ok((crc32, known_rom)) => {
+                println!("CRC-32: {:08x}", crc32);
+
+                match known_rom {
+                    Some(known_rom) => println!("Known ROM: {} (recommended: -quirks:{})", known_rom.name, known_rom.quirks),
+                    None => println!("Unknown ROM")
+                }
+            }
+            Err(e) => {
+                println!("{}", e);
+
+                exit(exit_code(&e))
+            }
+        }
+
+        return;
+    }
+
+    if parsed_args.validate_only && !test_pattern {
+        // Lists (address, opcode) of every unsupported instruction
+        match load_interpreter(&parsed_args, rom_path).map(|interpreter| interpreter.validate_rom()) {
+            Ok(invalid_opcodes) => {
+                for (address, opcode) in &invalid_opcodes {
+                    println!("Invalid opcode={:#06x} at address={:#04x}", opcode, address);
+                }
+
+                println!("Found {} invalid opcode(s)", invalid_opcodes.len());
+            }
+            Err(e) => {
+                println!("{}", e);
+
+                exit(exit_code(&e))
+            }
+        }
+
+        return;
+    }
+
+    if let (Some(menu_key), false) = (parsed_args.quirks_report, test_pattern) {
+        let res = load_interpreter(&parsed_args, rom_path)
+            .and_then(|mut interpreter| Ok(quirks_report::run(&mut interpreter, &parsed_args, menu_key)?));
+
+        match res {
+            Ok(results) => {
+                for (name, passed) in &results {
+                    println!("{}: {}", name, if *passed { "pass" } else { "FAIL" });
+                }
+
+                if results.iter().any(|(_, passed)| !passed) {
+                    exit(QUIRK_CHECK_FAILED_EXIT_CODE)
+                }
+            }
+            Err(e) => {
+                println!("{}", e);
+
+                exit(exit_code(&e))
+            }
+        }
+
+        return;
+    }
+
+    let res = match parsed_args.backend {
+        Backend::Sdl => run_sdl(parsed_args),
+        Backend::Terminal => run_terminal(&parsed_args),
+    };
+
+    match res {
         Ok(_) => (),
         Err(e) => {
             println!("{}", e);
 
-            exit(-1)
+            exit(exit_code(&e))
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use rusty_calico_c8::InterpreterError;
+
+    use super::*;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_is_help_request() {
+        assert!(is_help_request(&to_args(&["rusty-calico-c8"])));
+        assert!(is_help_request(&to_args(&["rusty-calico-c8", "help"])));
+        assert!(is_help_request(&to_args(&["rusty-calico-c8", "-h"])));
+        assert!(is_help_request(&to_args(&["rusty-calico-c8", "--help"])));
+        assert!(is_help_request(&to_args(&["rusty-calico-c8", "-no_sound", "rom.ch8"])));
+
+        assert!(!is_help_request(&to_args(&["rusty-calico-c8", "rom.ch8"])));
+        assert!(!is_help_request(&to_args(&["rusty-calico-c8", "rom.ch8", "-no_sound"])));
+        assert!(!is_help_request(&to_args(&["rusty-calico-c8", "-keys"])));
+        assert!(!is_help_request(&to_args(&["rusty-calico-c8", "-test_pattern", "-scale:5"])));
+        assert!(!is_help_request(&to_args(&["rusty-calico-c8", "-", "-no_sound"])));
+    }
+
+    #[test]
+    fn test_is_version_request() {
+        assert!(is_version_request(&to_args(&["rusty-calico-c8", "-version"])));
+        assert!(is_version_request(&to_args(&["rusty-calico-c8", "--version"])));
+
+        assert!(!is_version_request(&to_args(&["rusty-calico-c8"])));
+        assert!(!is_version_request(&to_args(&["rusty-calico-c8", "rom.ch8", "-version"])));
+        assert!(!is_version_request(&to_args(&["rusty-calico-c8", "-v"])));
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&EmulatorError::Frontend("No audio device".to_owned())), 1);
+        assert_eq!(exit_code(&EmulatorError::RomLoad("Unable to load 'rom.ch8'".to_owned())), 2);
+        assert_eq!(exit_code(&EmulatorError::Interpreter(InterpreterError::InvalidOpcode { pc: 0x200, opcode: 0xFFFF })), 3);
+        assert_eq!(exit_code(&EmulatorError::from(InterpreterError::StackUnderflow { pc: 0x202 })), 3);
+        assert_eq!(exit_code(&EmulatorError::from("Window closed".to_owned())), 1);
+    }
+
+    #[test]
+    fn test_format_default_key_grid() {
+        assert_eq!(format_key_grid(None), vec![
+            "1 = 1 | 2 = 2 | 3 = 3 | 4 = C",
+            "Q = 4 | W = 5 | E = 6 | R = D",
+            "A = 7 | S = 8 | D = 9 | F = E",
+            "Z = A | X = 0 | C = B | V = F",
+        ]);
+    }
+
+    #[test]
+    fn test_format_custom_key_grid() {
+        let keymap: Vec<String> = "Space,1,2,3,Q,W,E,A,S,D,Z,C,4,R,F,V".split(',').map(|key| key.to_owned()).collect();
+
+        let grid = format_key_grid(Some(&keymap));
+
+        assert_eq!(grid[0], "    1 = 1 |     2 = 2 |     3 = 3 |     4 = C");
+        assert_eq!(grid[3], "    Z = A | Space = 0 |     C = B |     V = F");
+    }
+}
@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+// One line per opcode category, most executed first, ties in opcode order so the output is stable
+pub fn format_opcode_counts(counts: &HashMap<u16, u64>) -> Vec<String> {
+    let total: u64 = counts.values().sum();
+
+    let mut sorted: Vec<_> = counts.iter().collect();
+    sorted.sort_by(|(opcode_a, count_a), (opcode_b, count_b)| count_b.cmp(count_a).then(opcode_a.cmp(opcode_b)));
+
+    sorted.iter()
+        .map(|(opcode, count)| format!("{:04X}: {} ({:.1}%)", opcode, count, **count as f64 * 100.0 / total as f64))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_opcode_counts() {
+        let counts = HashMap::from([(0xD000, 25), (0x1000, 50), (0x6000, 25)]);
+
+        assert_eq!(format_opcode_counts(&counts), vec![
+            "1000: 50 (50.0%)",
+            "6000: 25 (25.0%)",
+            "D000: 25 (25.0%)",
+        ]);
+
+        assert!(format_opcode_counts(&HashMap::new()).is_empty());
+    }
+}
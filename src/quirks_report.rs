@@ -0,0 +1,98 @@
+use std::ops::Range;
+
+use rusty_calico_c8::{Chip8Interpreter, FrameBuffer, InterpreterError};
+
+use crate::cmd_args::ApplicationCmdSettings;
+
+// -quirks_report, runs Timendus' quirks test ROM (5-quirks.ch8) without a frontend and reads the results off
+// its lores result screen. One line per quirk, the result glyph (5 x 5) is drawn in the rightmost column:
+//   check mark      cross
+//   ....#           #...#
+//   ...#.           .#.#.
+//   #.#..           ..#..
+//   .#...           .#.#.
+//   .....           #...#
+// The top right pixel is only lit by the two glyphs and the top left one only by the cross.
+
+struct QuirkCheck {
+    name: &'static str,
+    // Top left corner of the result glyph
+    x: u8,
+    y: u8,
+}
+
+const QUIRK_CHECKS: [QuirkCheck; 6] = [
+    QuirkCheck { name: "vF reset", x: 57, y: 1 },
+    QuirkCheck { name: "memory", x: 57, y: 6 },
+    QuirkCheck { name: "display wait", x: 57, y: 11 },
+    QuirkCheck { name: "clipping", x: 57, y: 16 },
+    QuirkCheck { name: "shifting", x: 57, y: 21 },
+    QuirkCheck { name: "jumping", x: 57, y: 26 },
+];
+
+// Long enough for the display wait check, which counts frames
+const REPORT_FRAMES: u64 = 600;
+
+// The ROM starts with a platform menu, the menu key is held for a few frames once it's up
+const MENU_KEY_FRAMES: Range<u64> = 30..36;
+
+// (quirk name, passed), in QUIRK_CHECKS order. A missing glyph counts as failed
+pub fn evaluate(frame_buffer: &FrameBuffer) -> Vec<(&'static str, bool)> {
+    QUIRK_CHECKS.iter()
+        .map(|check| {
+            let passed = frame_buffer.get_pixel(check.x + 4, check.y) && frame_buffer.get_pixel(check.x + 1, check.y + 3) &&
+                !frame_buffer.get_pixel(check.x, check.y);
+
+            (check.name, passed)
+        })
+        .collect()
+}
+
+pub fn run(interpreter: &mut Chip8Interpreter, parsed_args: &ApplicationCmdSettings,
+           menu_key: u8) -> Result<Vec<(&'static str, bool)>, InterpreterError> {
+    let mut cycle_remainder = 0;
+
+    for frame in 0..REPORT_FRAMES {
+        interpreter.set_key(menu_key, MENU_KEY_FRAMES.contains(&frame));
+        interpreter.run_frame(parsed_args.next_frame_cycles(&mut cycle_remainder))?;
+    }
+
+    Ok(evaluate(&interpreter.frame_buffer))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CHECK_MARK: [(u8, u8); 5] = [(4, 0), (3, 1), (0, 2), (2, 2), (1, 3)];
+    const CROSS: [(u8, u8); 9] = [(0, 0), (4, 0), (1, 1), (3, 1), (2, 2), (1, 3), (3, 3), (0, 4), (4, 4)];
+
+    fn draw_glyph(frame_buffer: &mut FrameBuffer, check: &QuirkCheck, glyph: &[(u8, u8)]) {
+        for (x, y) in glyph {
+            if !frame_buffer.get_pixel(check.x + x, check.y + y) {
+                frame_buffer.flip_pixel(check.x + x, check.y + y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        for check in &QUIRK_CHECKS[..4] {
+            draw_glyph(&mut frame_buffer, check, &CHECK_MARK);
+        }
+
+        draw_glyph(&mut frame_buffer, &QUIRK_CHECKS[4], &CROSS);
+
+        // Last one left blank
+        assert_eq!(evaluate(&frame_buffer), vec![
+            ("vF reset", true),
+            ("memory", true),
+            ("display wait", true),
+            ("clipping", true),
+            ("shifting", false),
+            ("jumping", false),
+        ]);
+    }
+}
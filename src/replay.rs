@@ -0,0 +1,117 @@
+use std::io::{BufRead, Write};
+
+// Replay files are plain text:
+//   calico-replay 1
+//   seed <rng seed>
+//   <frame> <keypad bitmask in hex>
+//   ...
+// The seed is stored so Cxnn produces the same numbers on playback, otherwise inputs desync quickly.
+
+const REPLAY_MAGIC: &str = "calico-replay 1";
+
+pub struct Recorder<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut writer: W, seed: u64) -> Result<Recorder<W>, std::io::Error> {
+        writeln!(writer, "{}", REPLAY_MAGIC)?;
+        writeln!(writer, "seed {}", seed)?;
+
+        Ok(Recorder { writer })
+    }
+
+    pub fn record_frame(&mut self, frame: u64, keypad_state: u16) -> Result<(), std::io::Error> {
+        writeln!(self.writer, "{} {:04x}", frame, keypad_state)
+    }
+}
+
+pub struct Player {
+    seed: u64,
+    keypad_states: Vec<u16>,
+}
+
+impl Player {
+    pub fn new<R: BufRead>(reader: R) -> Result<Player, String> {
+        let mut lines = reader.lines();
+
+        let mut next_line = || -> Result<Option<String>, String> {
+            lines.next().transpose().map_err(|e| e.to_string())
+        };
+
+        if next_line()?.as_deref() != Some(REPLAY_MAGIC) {
+            return Err("Not a replay file".to_owned());
+        }
+
+        let seed = match next_line()? {
+            Some(line) if line.starts_with("seed ") => line[5..].parse()
+                .map_err(|_| format!("Invalid replay seed '{}'", &line[5..]))?,
+            _ => return Err("Replay file is missing the seed".to_owned())
+        };
+
+        let mut keypad_states = vec![];
+
+        while let Some(line) = next_line()? {
+            let tokens: Vec<_> = line.split_whitespace().collect();
+
+            let (frame, keypad_state) = match tokens[..] {
+                [frame, keypad_state] => (frame.parse::<u64>().ok(), u16::from_str_radix(keypad_state, 16).ok()),
+                _ => (None, None)
+            };
+
+            match (frame, keypad_state) {
+                (Some(frame), Some(keypad_state)) if frame == keypad_states.len() as u64 => {
+                    keypad_states.push(keypad_state)
+                }
+
+                _ => return Err(format!("Invalid replay line '{}'", line))
+            }
+        }
+
+        Ok(Player { seed, keypad_states })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Nothing is held once the recording runs out
+    pub fn keypad_state(&self, frame: u64) -> u16 {
+        self.keypad_states.get(frame as usize).copied().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_and_play_back() {
+        let inputs = [0x0000, 0x0001, 0x8001, 0x0000, 0xFFFF];
+        let mut buffer = vec![];
+
+        let mut recorder = Recorder::new(&mut buffer, 987654321).unwrap();
+
+        for (frame, keypad_state) in inputs.iter().enumerate() {
+            recorder.record_frame(frame as u64, *keypad_state).unwrap();
+        }
+
+        let player = Player::new(&buffer[..]).unwrap();
+
+        assert_eq!(player.seed(), 987654321);
+
+        for (frame, keypad_state) in inputs.iter().enumerate() {
+            assert_eq!(player.keypad_state(frame as u64), *keypad_state);
+        }
+
+        assert_eq!(player.keypad_state(5), 0);
+    }
+
+    #[test]
+    fn test_invalid_replay() {
+        assert!(Player::new(&b"something else\nseed 1\n"[..]).is_err());
+        assert!(Player::new(&b"calico-replay 1\n0 0000\n"[..]).is_err());
+        assert!(Player::new(&b"calico-replay 1\nseed 1\n0 0000\n2 0001\n"[..]).is_err());
+        assert!(Player::new(&b"calico-replay 1\nseed 1\n0 zzzz\n"[..]).is_err());
+    }
+}
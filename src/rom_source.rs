@@ -0,0 +1,65 @@
+use std::io::{Error, ErrorKind, Read};
+
+use rusty_calico_c8::{Chip8Interpreter, InterpreterError};
+
+// Given instead of a ROM path, reads the ROM from stdin so it can be piped in from other tools
+pub const STDIN_ROM_PATH: &str = "-";
+
+pub fn is_stdin(rom_path: &str) -> bool {
+    rom_path == STDIN_ROM_PATH
+}
+
+// Stdin can only be read once, coming back to it in a playlist finds nothing and is skipped like any broken ROM
+pub fn read_rom<R: Read>(mut reader: R) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+
+    reader.read_to_end(&mut data)?;
+
+    if data.is_empty() {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "No ROM data on stdin"));
+    }
+
+    Ok(data)
+}
+
+// Chip8Interpreter::load_rom() that also understands STDIN_ROM_PATH
+pub fn load(interpreter: &mut Chip8Interpreter, rom_path: &str) -> Result<(), InterpreterError> {
+    if is_stdin(rom_path) {
+        let data = read_rom(std::io::stdin().lock())
+            .map_err(|e| InterpreterError::RomUnreadable { reason: e.to_string() })?;
+
+        interpreter.load_rom_bytes(&data)
+    } else {
+        interpreter.load_rom(rom_path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rusty_calico_c8::Chip8Config;
+
+    use super::*;
+
+    #[test]
+    fn test_is_stdin() {
+        assert!(is_stdin("-"));
+
+        assert!(!is_stdin("rom.ch8"));
+        assert!(!is_stdin("-no_sound"));
+        assert!(!is_stdin("./-"));
+    }
+
+    #[test]
+    fn test_read_rom() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        interpreter.load_rom_bytes(&read_rom(&[0x12, 0x00][..]).unwrap()).unwrap();
+
+        assert_eq!(interpreter.rom(), &[0x12, 0x00]);
+        assert_eq!(read_rom(&[][..]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        // Same size limit as ROM files
+        let too_big = read_rom(&[0u8; 4096][..]).unwrap();
+        assert!(interpreter.load_rom_bytes(&too_big).is_err());
+    }
+}
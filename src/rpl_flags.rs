@@ -0,0 +1,45 @@
+use rusty_calico_c8::Chip8Interpreter;
+
+// -rpl_flags, the file is just the raw flag bytes. Not existing yet is fine, it's created on exit.
+pub fn load(path: &str, interpreter: &mut Chip8Interpreter) -> Result<(), String> {
+    match std::fs::read(path) {
+        Ok(flags) => interpreter.set_rpl_flags(&flags),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(format!("Unable to read RPL flags '{}': {}", path, e))
+    }
+
+    Ok(())
+}
+
+pub fn save(path: &str, interpreter: &Chip8Interpreter) -> Result<(), String> {
+    std::fs::write(path, interpreter.rpl_flags())
+        .map_err(|e| format!("Unable to write RPL flags '{}': {}", path, e))
+}
+
+#[cfg(test)]
+mod test {
+    use rusty_calico_c8::Chip8Config;
+
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("rusty-calico-c8-rpl-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+        // Nothing saved yet
+        load(path, &mut interpreter).unwrap();
+        assert!(interpreter.rpl_flags().iter().all(|flag| *flag == 0));
+
+        interpreter.set_rpl_flags(&[1, 2, 3]);
+        save(path, &interpreter).unwrap();
+
+        let mut restored = Chip8Interpreter::new(Chip8Config::default());
+        load(path, &mut restored).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(restored.rpl_flags(), interpreter.rpl_flags());
+    }
+}
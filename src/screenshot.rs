@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusty_calico_c8::FrameBuffer;
+use rusty_calico_c8::gzip;
+
+// Tightly packed RGB24, one 'fg' or 'bg' pixel per frame buffer pixel
+pub fn frame_buffer_to_rgb(frame_buffer: &FrameBuffer, fg: [u8; 3], bg: [u8; 3]) -> Vec<u8> {
+    let (width, height) = frame_buffer.resolution();
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 3);
+
+    for y in 0..height {
+        for x in 0..width {
+            let color = if frame_buffer.pixel_value(x, y) != 0 { fg } else { bg };
+
+            pixels.extend_from_slice(&color);
+        }
+    }
+
+    pixels
+}
+
+// Writes the frame buffer to screenshot-<unix timestamp>.png and returns the file name
+pub fn save_screenshot(frame_buffer: &FrameBuffer, fg: [u8; 3], bg: [u8; 3]) -> Result<String, std::io::Error> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let file_name = format!("screenshot-{}.png", timestamp);
+    let (width, height) = frame_buffer.resolution();
+    let png = encode_png(width as u32, height as u32, &frame_buffer_to_rgb(frame_buffer, fg, bg));
+
+    File::create(&file_name)?.write_all(&png)?;
+
+    Ok(file_name)
+}
+
+// Minimal RGB24 PNG encoder, the image data is stored uncompressed (deflate block type 0)
+// which is plenty for a 64x32 screen and saves pulling in an image crate
+pub fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bit depth, truecolor, default compression, filter and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    write_png_chunk(&mut png, b"IHDR", &header);
+
+    // Every scanline starts with its filter type, 0 = none
+    let row_len = width as usize * 3;
+    let mut scanlines = Vec::with_capacity((row_len + 1) * height as usize);
+
+    for row in rgb.chunks(row_len) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    write_png_chunk(&mut png, b"IDAT", &zlib_store(&scanlines));
+    write_png_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let crc_start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+
+    let crc = gzip::crc32(&png[crc_start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_store(data: &[u8]) -> Vec<u8> {
+    // CMF/FLG for deflate with a 32K window and no preset dictionary
+    let mut res = vec![0x78, 0x01];
+
+    let blocks: Vec<_> = data.chunks(0xFFFF).collect();
+
+    if blocks.is_empty() {
+        res.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    for (i, block) in blocks.iter().enumerate() {
+        let len = block.len() as u16;
+
+        res.push((i == blocks.len() - 1) as u8);
+        res.extend_from_slice(&len.to_le_bytes());
+        res.extend_from_slice(&(!len).to_le_bytes());
+        res.extend_from_slice(block);
+    }
+
+    res.extend_from_slice(&adler32(data).to_be_bytes());
+
+    res
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frame_buffer_to_rgb() {
+        let mut frame_buffer = FrameBuffer::new();
+
+        frame_buffer.flip_pixel(1, 0);
+
+        let pixels = frame_buffer_to_rgb(&frame_buffer, [255, 128, 0], [0, 0, 64]);
+
+        assert_eq!(pixels.len(), 64 * 32 * 3);
+        assert_eq!(&pixels[0..3], &[0, 0, 64]);
+        assert_eq!(&pixels[3..6], &[255, 128, 0]);
+        assert_eq!(&pixels[6..9], &[0, 0, 64]);
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn test_encode_png_layout() {
+        let png = encode_png(2, 1, &[255, 0, 0, 0, 255, 0]);
+
+        assert_eq!(&png[0..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..20], &2u32.to_be_bytes());
+        assert_eq!(&png[20..24], &1u32.to_be_bytes());
+        assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+    }
+}
@@ -0,0 +1,21 @@
+use rusty_calico_c8::Chip8Interpreter;
+
+use crate::emulator_error::EmulatorError;
+
+// -dump_state_on_error, only interpreter errors are worth a dump, the ROM's state is what went wrong there
+#[cfg(feature = "serde")]
+pub fn dump_on_error(path: Option<&str>, interpreter: &Chip8Interpreter, res: &Result<(), EmulatorError>) {
+    if let (Some(path), Err(EmulatorError::Interpreter(_))) = (path, res) {
+        // Memory and screen included, registers alone rarely explain a crash
+        let include_buffers = true;
+
+        match std::fs::write(path, interpreter.state_json(include_buffers)) {
+            Ok(_) => eprintln!("Interpreter state written to '{}'", path),
+            Err(e) => eprintln!("Unable to write state dump '{}': {}", path, e)
+        }
+    }
+}
+
+// main() refuses -dump_state_on_error in builds without serde, so there's never a path to write to
+#[cfg(not(feature = "serde"))]
+pub fn dump_on_error(_path: Option<&str>, _interpreter: &Chip8Interpreter, _res: &Result<(), EmulatorError>) {}
@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use rusty_calico_c8::disassembler;
+
+// One 'PC OPCODE MNEMONIC' line per executed instruction, e.g. '0200 6a05 LD VA, 0x05'
+pub struct TraceWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> TraceWriter<W> {
+        TraceWriter { writer }
+    }
+
+    pub fn write_instructions(&mut self, instructions: impl Iterator<Item=(u16, u16)>) -> Result<(), std::io::Error> {
+        for (pc, opcode) in instructions {
+            writeln!(self.writer, "{:04x} {:04x} {}", pc, opcode, disassembler::decode(opcode).mnemonic)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.writer.flush()
+    }
+}
+
+// Buffered since a line is written per instruction, hundreds of thousands of them a minute at the default speed
+pub fn create(path: &str) -> Result<TraceWriter<BufWriter<File>>, String> {
+    let file = File::create(path)
+        .map_err(|e| format!("Unable to create trace '{}': {}", path, e))?;
+
+    eprintln!("Tracing every instruction to '{}', the file grows quickly", path);
+
+    Ok(TraceWriter::new(BufWriter::new(file)))
+}
+
+#[cfg(test)]
+mod test {
+    use rusty_calico_c8::{Chip8Config, Chip8Interpreter};
+
+    use super::*;
+
+    #[test]
+    fn test_trace_lines_for_short_rom() {
+        let mut interpreter = Chip8Interpreter::new(Chip8Config { log_executions: true, ..Chip8Config::default() });
+
+        // 0x200: VA = 0x05, 0x202: VB = 0x10, 0x204: jump to 0x204
+        interpreter.load_rom_bytes(&[0x6A, 0x05, 0x6B, 0x10, 0x12, 0x04]).unwrap();
+
+        let mut trace = TraceWriter::new(vec![]);
+
+        for _ in 0..3 {
+            interpreter.run_frame(10).unwrap();
+            trace.write_instructions(interpreter.drain_execution_log()).unwrap();
+        }
+
+        let text = String::from_utf8(trace.writer).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+
+        assert_eq!(lines.len(), 30);
+        assert_eq!(lines[0], "0200 6a05 LD VA, 0x05");
+        assert_eq!(lines[29], "0204 1204 JP 0x204");
+    }
+}
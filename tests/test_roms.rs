@@ -0,0 +1,44 @@
+use rusty_calico_c8::{Chip8Config, Chip8Interpreter};
+
+// Small self checking ROM in the spirit of the flags test, every result (and VF where it matters) is drawn
+// as a hex digit so a wrong opcode shows up as a different picture:
+//   row 1: 8xy4 = 8, 8xy4 carry = 1 1, 8xy5 = 2 1, 8xy6 = 3 0, 8xyE = 2 1
+//   row 2: Fx33/Fx65 of 156 = 1 5 6, 2nnn/00EE + 3xnn = 9
+const FLAGS_ROM: [u16; 71] = [
+    0x6A00, 0x6B00, 0x6005, 0x6103, 0x8014, 0xF029, 0xDAB5, 0x7A05,
+    0x62FF, 0x6302, 0x8234, 0x8EF0, 0xF229, 0xDAB5, 0x7A05, 0xFE29,
+    0xDAB5, 0x7A05, 0x6505, 0x6403, 0x8545, 0x8EF0, 0xF529, 0xDAB5,
+    0x7A05, 0xFE29, 0xDAB5, 0x7A05, 0x6606, 0x8666, 0x8EF0, 0xF629,
+    0xDAB5, 0x7A05, 0xFE29, 0xDAB5, 0x7A05, 0x6781, 0x877E, 0x8EF0,
+    0xF729, 0xDAB5, 0x7A05, 0xFE29, 0xDAB5, 0x7A05, 0x6A00, 0x6B08,
+    0x689C, 0xA300, 0xF833, 0xF265, 0xF029, 0xDAB5, 0x7A05, 0xF129,
+    0xDAB5, 0x7A05, 0xF229, 0xDAB5, 0x7A05, 0x6C00, 0x228A, 0x3C09,
+    0x6C00, 0xFC29, 0xDAB5, 0x7A05, 0x1288, 0x6C09, 0x00EE,
+];
+
+const FLAGS_ROM_EXPECTED_HASH: u64 = 4399279571893938974;
+
+fn rom_bytes(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+fn run_rom(rom: &[u8], frames: u32) -> Chip8Interpreter {
+    let mut interpreter = Chip8Interpreter::new(Chip8Config::default());
+
+    interpreter.load_rom_bytes(rom).unwrap();
+
+    for _ in 0..frames {
+        interpreter.run_frame(10).unwrap();
+    }
+
+    interpreter
+}
+
+#[test]
+fn test_flags_rom() {
+    let interpreter = run_rom(&rom_bytes(&FLAGS_ROM), 30);
+
+    let hash = interpreter.frame_buffer.frame_hash();
+
+    assert_eq!(hash, FLAGS_ROM_EXPECTED_HASH, "unexpected frame buffer:\n{}", interpreter.frame_buffer.to_ascii());
+}